@@ -0,0 +1,56 @@
+//! Recursive Fibonacci, run by the tree-walking evaluator and by the VM.
+//!
+//! Run with `cargo bench --bench fibonacci`. Both engines must agree on the
+//! result; compilation is timed as part of the VM run.
+
+use monkey_rs::compiler::Compiler;
+use monkey_rs::vm::Vm;
+use monkey_rs::{eval, parse, Environment};
+use std::time::{Duration, Instant};
+
+const INPUT: &str = "
+let fibonacci = fn(x) {
+  if (x < 2) { x } else { fibonacci(x - 1) + fibonacci(x - 2) }
+};
+fibonacci(25);
+";
+
+fn run_eval() -> String {
+    let program = parse(INPUT).unwrap();
+    eval(&program, &mut Environment::new()).unwrap().to_string()
+}
+
+fn run_vm() -> String {
+    let program = parse(INPUT).unwrap();
+    let mut compiler = Compiler::new();
+    compiler.compile(&program).unwrap();
+
+    let mut vm = Vm::new(compiler.bytecode());
+    vm.run().unwrap();
+    vm.last_popped_stack_elem().unwrap().to_string()
+}
+
+fn time<F: FnMut() -> String>(mut f: F) -> (String, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = String::new();
+    for _ in 0..3 {
+        let start = Instant::now();
+        result = std::hint::black_box(f());
+        best = best.min(start.elapsed());
+    }
+    (result, best)
+}
+
+fn main() {
+    let (eval_result, eval_elapsed) = time(run_eval);
+    let (vm_result, vm_elapsed) = time(run_vm);
+    assert_eq!(eval_result, vm_result);
+
+    println!("fibonacci(25) = {}", vm_result);
+    println!("eval {:>10.3?}", eval_elapsed);
+    println!(
+        "vm   {:>10.3?} ({:.1}x faster)",
+        vm_elapsed,
+        eval_elapsed.as_secs_f64() / vm_elapsed.as_secs_f64()
+    );
+}
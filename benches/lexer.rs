@@ -0,0 +1,73 @@
+//! Lexing throughput on generated scripts of growing size.
+//!
+//! Run with `cargo bench --bench lexer`. A linear lexer keeps roughly the same
+//! MB/s at every size; the `chars().nth()` scan the lexer used to do per
+//! character is timed alongside for comparison, on the smaller inputs only.
+
+use monkey_rs::lexer::Lexer;
+use std::time::{Duration, Instant};
+
+const CHUNK: &str = "let add = fn(x, y) {
+  if (x < y) { return x + y * 2; } else { return !(x == y); }
+};
+let result = add(five, ten) - 10 / 2;
+";
+
+fn script(bytes: usize) -> String {
+    CHUNK.repeat(bytes / CHUNK.len() + 1)
+}
+
+fn lex(input: &str) -> usize {
+    Lexer::new(input).count()
+}
+
+/// Walks the input the way the old lexer did, restarting from the beginning of
+/// the string for every character.
+fn nth_scan(input: &str) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while let Some(ch) = input.chars().nth(i) {
+        if !ch.is_whitespace() {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn megabytes_per_second(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
+
+fn main() {
+    for &size in &[10_000, 100_000, 1_000_000, 10_000_000] {
+        let input = script(size);
+        let elapsed = time(|| lex(&input));
+        print!(
+            "{:>9} bytes  lexer {:>10.3?} ({:>7.1} MB/s)",
+            input.len(),
+            elapsed,
+            megabytes_per_second(input.len(), elapsed)
+        );
+
+        if size <= 100_000 {
+            let elapsed = time(|| nth_scan(&input));
+            print!(
+                "  chars().nth() {:>10.3?} ({:>7.1} MB/s)",
+                elapsed,
+                megabytes_per_second(input.len(), elapsed)
+            );
+        }
+        println!();
+    }
+}
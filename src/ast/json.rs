@@ -0,0 +1,495 @@
+//! The AST as JSON, in the shape described in [`serialize`](super::serialize).
+//!
+//! ```
+//! use monkey_rs::ast::{json, Program};
+//!
+//! let program = monkey_rs::parse("-x").unwrap();
+//! let text = json::to_string(&program);
+//! assert!(text.starts_with("{\n  \"type\": \"Program\",\n"));
+//!
+//! let decoded: Program = json::from_str(&text).unwrap();
+//! assert_eq!(json::to_string(&decoded), text);
+//! ```
+
+use super::serialize::{Decode, DecodeError, Encode, Value};
+
+/// Lines are broken to keep them within this many bytes, where possible.
+const WIDTH: usize = 80;
+const MAX_DEPTH: usize = 512;
+
+/// `node` as indented JSON, ending with a newline.
+pub fn to_string<T: Encode + ?Sized>(node: &T) -> String {
+    let mut out = String::new();
+    write(&node.encode(), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+pub fn from_str<T: Decode>(source: &str) -> Result<T, DecodeError> {
+    T::decode(&read(source)?)
+}
+
+/// Writes `value` on what's left of the line if it fits, and with one item or
+/// entry per line otherwise.
+pub fn write(value: &Value, indent: usize, out: &mut String) {
+    // NOTE: giving up on the one-line form once it's too long keeps this
+    //       linear; building it whole at every level would be quadratic
+    let column = out.len() - out.rfind('\n').map_or(0, |i| i + 1);
+    let mut line = String::new();
+    if compact(value, WIDTH.saturating_sub(column), &mut line) {
+        out.push_str(&line);
+        return;
+    }
+
+    match value {
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(indent + 1, out);
+                write(item, indent + 1, out);
+            }
+            newline(indent, out);
+            out.push(']');
+        }
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(indent + 1, out);
+                out.push_str(&quote(key));
+                out.push_str(": ");
+                write(value, indent + 1, out);
+            }
+            newline(indent, out);
+            out.push('}');
+        }
+        _ => {
+            compact(value, usize::MAX, out);
+        }
+    }
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&"  ".repeat(indent));
+}
+
+/// Writes `value` on one line, as long as that takes at most `limit` bytes.
+/// Returns whether it did; past the limit, what was written is cut short.
+fn compact(value: &Value, limit: usize, out: &mut String) -> bool {
+    let end = out.len().saturating_add(limit);
+    match value {
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        // NOTE: quoting never makes a string shorter
+        Value::String(s) if out.len() + s.len() + 2 > end => return false,
+        Value::String(s) => out.push_str(&quote(s)),
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                if !compact(item, end.saturating_sub(out.len()), out) {
+                    return false;
+                }
+            }
+            out.push(']');
+        }
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&quote(key));
+                out.push_str(": ");
+                if !compact(value, end.saturating_sub(out.len()), out) {
+                    return false;
+                }
+            }
+            out.push('}');
+        }
+    }
+    out.len() <= end
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ch if ch < ' ' || ch == '\u{7f}' => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn read(source: &str) -> Result<Value, DecodeError> {
+    let mut reader = Reader {
+        source,
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < source.len() {
+        return Err(reader.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+struct Reader<'s> {
+    source: &'s str,
+    position: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: &str) -> DecodeError {
+        DecodeError::at(message, self.source, self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    /// Skips whitespace and `expected`, if that's what comes next.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DecodeError> {
+        if self.eat(expected) {
+            Ok(())
+        } else if self.position == self.source.len() {
+            Err(self.error("unexpected end of input"))
+        } else {
+            Err(self.error(&format!("expected {}", expected)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') | Some('[') => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(self.error("nested too deeply"));
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some('{') {
+                    self.map()
+                } else {
+                    self.list()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => self.string().map(Value::String),
+            Some('-') | Some('0'..='9') => self.int(),
+            Some(ch) if ch.is_ascii_alphabetic() => {
+                let start = self.position;
+                while self.peek().is_some_and(|ch| ch.is_ascii_alphabetic()) {
+                    self.position += 1;
+                }
+                match &self.source[start..self.position] {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => {
+                        self.position = start;
+                        Err(self.error("expected a value"))
+                    }
+                }
+            }
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, DecodeError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        if self.eat(']') {
+            return Ok(Value::List(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(']') {
+                return Ok(Value::List(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn map(&mut self) -> Result<Value, DecodeError> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        if self.eat('}') {
+            return Ok(Value::Map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            if self.eat('}') {
+                return Ok(Value::Map(entries));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn int(&mut self) -> Result<Value, DecodeError> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if let Some('.' | 'e' | 'E') = self.peek() {
+            return Err(self.error("expected an integer"));
+        }
+        let text = &self.source[start..self.position];
+        text.parse().map(Value::Int).map_err(|_| {
+            self.position = start;
+            self.error("invalid integer")
+        })
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.position += 1;
+        let mut out = String::new();
+        loop {
+            let start = self.position;
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let ch = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape(start)?,
+                        _ => {
+                            self.position = start;
+                            return Err(self.error("invalid escape"));
+                        }
+                    };
+                    out.push(ch);
+                }
+                Some(ch) if ch < ' ' => {
+                    self.position = start;
+                    return Err(self.error("control character in string"));
+                }
+                Some(ch) => out.push(ch),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape starting at `start`, whose `\u` has been
+    /// read. Characters outside the Basic Multilingual Plane take two escapes.
+    fn unicode_escape(&mut self, start: usize) -> Result<char, DecodeError> {
+        let mut code = self.hex4(start)?;
+        if (0xd800..0xdc00).contains(&code) && self.source[self.position..].starts_with("\\u") {
+            self.position += 2;
+            let low = self.hex4(start)?;
+            if (0xdc00..0xe000).contains(&low) {
+                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+            }
+        }
+        char::from_u32(code).ok_or_else(|| {
+            self.position = start;
+            self.error("invalid escape")
+        })
+    }
+
+    fn hex4(&mut self, start: usize) -> Result<u32, DecodeError> {
+        // NOTE: from_str_radix alone would also take a sign, as in `\u+041`
+        let digits = self
+            .source
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()));
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(code) => {
+                self.position += 4;
+                Ok(code)
+            }
+            None => {
+                self.position = start;
+                Err(self.error("invalid escape"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Program;
+    use crate::parser::parse;
+
+    #[test]
+    fn output() {
+        let program = parse("let x = [1, true];").unwrap();
+        assert_eq!(
+            to_string(&program),
+            r#"{
+  "type": "Program",
+  "statements": [
+    {
+      "type": "LetStatement",
+      "token": {
+        "type": "Let",
+        "literal": "let",
+        "span": {"start": 0, "end": 3, "line": 1, "column": 1}
+      },
+      "name": {
+        "type": "Ident",
+        "literal": "x",
+        "span": {"start": 4, "end": 5, "line": 1, "column": 5}
+      },
+      "value": {
+        "type": "ArrayLiteral",
+        "token": {
+          "type": "LeftBracket",
+          "literal": "[",
+          "span": {"start": 8, "end": 9, "line": 1, "column": 9}
+        },
+        "elements": [
+          {
+            "type": "IntegerLiteral",
+            "token": {
+              "type": "Int",
+              "literal": "1",
+              "span": {"start": 9, "end": 10, "line": 1, "column": 10}
+            },
+            "value": 1
+          },
+          {
+            "type": "Boolean",
+            "token": {
+              "type": "True",
+              "literal": "true",
+              "span": {"start": 12, "end": 16, "line": 1, "column": 13}
+            },
+            "value": true
+          }
+        ]
+      }
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = "let s = \"tab\\t \\\"quote\\\" \u{e9} \u{1f600}\";\n\
+                      if (s) { fn(a) { a[-1] } } else { {1: [], \"k\": {}} }";
+        let program = parse(source).unwrap();
+        let text = to_string(&program);
+        let decoded: Program = from_str(&text).unwrap();
+        assert_eq!(to_string(&decoded), text);
+    }
+
+    #[test]
+    fn values() {
+        let tests = vec![
+            (" true ", Value::Bool(true)),
+            ("-42", Value::Int(-42)),
+            (
+                r#""aé😀\n\/""#,
+                Value::String("a\u{e9}\u{1f600}\n/".to_string()),
+            ),
+            ("[ ]", Value::List(vec![])),
+            (
+                r#"{"a": [1, false], "b": {}}"#,
+                Value::Map(vec![
+                    (
+                        "a".to_string(),
+                        Value::List(vec![Value::Int(1), Value::Bool(false)]),
+                    ),
+                    ("b".to_string(), Value::Map(vec![])),
+                ]),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(read(input), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let tests = vec![
+            ("", "unexpected end of input at 1:1"),
+            ("[1,\n 2", "unexpected end of input at 2:3"),
+            ("[1 2]", "expected , at 1:4"),
+            ("{1: 2}", "expected a key at 1:2"),
+            ("1.5", "expected an integer at 1:2"),
+            ("99999999999999999999", "invalid integer at 1:1"),
+            ("nul", "expected a value at 1:1"),
+            (r#""\x""#, "invalid escape at 1:2"),
+            (r#""\u+041""#, "invalid escape at 1:2"),
+            (r#""\u-041""#, "invalid escape at 1:2"),
+            (r#""\u 041""#, "invalid escape at 1:2"),
+            (r#""\u004""#, "invalid escape at 1:2"),
+            ("\"abc", "unterminated string at 1:5"),
+            ("[] []", "unexpected text after the value at 1:4"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(read(input).unwrap_err().to_string(), expected, "{}", input);
+        }
+        assert_eq!(
+            read(&"[".repeat(MAX_DEPTH + 1)).unwrap_err().to_string(),
+            format!("nested too deeply at 1:{}", MAX_DEPTH + 1)
+        );
+        assert_eq!(
+            from_str::<Program>(r#"{"type": "Program"}"#)
+                .unwrap_err()
+                .to_string(),
+            "missing statements in Program"
+        );
+    }
+}
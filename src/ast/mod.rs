@@ -1,25 +1,35 @@
-use crate::token::{Token, TokenType};
-use std::any::Any;
-
-fn dummy_identifier() -> Expression {
-    Expression::Identifier {
-        token: Token {
-            literal: "".to_string(),
-            t: TokenType::Ident,
-        },
-        value: "".to_string(),
-    }
-}
+pub mod json;
+pub mod serialize;
+pub mod sexpr;
+pub mod visit;
+
+pub use self::serialize::{Decode, DecodeError, Encode};
+pub use self::visit::{Fold, Visitor, VisitorMut};
+
+use crate::token::Token;
+use std::borrow::Cow;
+use std::rc::Rc;
 
 pub trait Node {
     fn string(&self) -> String;
 }
 
-pub struct Program {
-    pub statements: Vec<Statement>,
+#[derive(Debug, Clone)]
+pub struct Program<'a> {
+    pub statements: Vec<Statement<'a>>,
 }
 
-impl Node for Program {
+impl<'a> Program<'a> {
+    /// A copy that no longer borrows from the source, for a program whose
+    /// values have to outlive it.
+    pub fn into_owned(self) -> Program<'static> {
+        Program {
+            statements: owned_statements(self.statements),
+        }
+    }
+}
+
+impl Node for Program<'_> {
     fn string(&self) -> String {
         let mut out = String::new();
         for s in &self.statements {
@@ -30,23 +40,43 @@ impl Node for Program {
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub enum Statement<'a> {
     Let {
-        token: Token,
-        name: Token,
-        value: Expression,
+        token: Token<'a>,
+        name: Token<'a>,
+        value: Expression<'a>,
     },
     Return {
-        token: Token,
-        value: Expression,
+        token: Token<'a>,
+        value: Expression<'a>,
     },
     Expression {
-        token: Token,
-        expression: Expression,
+        token: Token<'a>,
+        expression: Expression<'a>,
     },
 }
 
-impl Node for Statement {
+impl<'a> Statement<'a> {
+    pub fn into_owned(self) -> Statement<'static> {
+        match self {
+            Statement::Let { token, name, value } => Statement::Let {
+                token: token.into_owned(),
+                name: name.into_owned(),
+                value: value.into_owned(),
+            },
+            Statement::Return { token, value } => Statement::Return {
+                token: token.into_owned(),
+                value: value.into_owned(),
+            },
+            Statement::Expression { token, expression } => Statement::Expression {
+                token: token.into_owned(),
+                expression: expression.into_owned(),
+            },
+        }
+    }
+}
+
+impl Node for Statement<'_> {
     fn string(&self) -> String {
         match self {
             Statement::Let {
@@ -61,34 +91,225 @@ impl Node for Statement {
 }
 
 #[derive(Debug, Clone)]
-pub enum Expression {
+pub struct BlockStatement<'a> {
+    pub token: Token<'a>,
+    pub statements: Vec<Statement<'a>>,
+}
+
+impl<'a> BlockStatement<'a> {
+    pub fn into_owned(self) -> BlockStatement<'static> {
+        BlockStatement {
+            token: self.token.into_owned(),
+            statements: owned_statements(self.statements),
+        }
+    }
+}
+
+impl Node for BlockStatement<'_> {
+    fn string(&self) -> String {
+        if self.statements.is_empty() {
+            return "{}".to_string();
+        }
+        let statements: Vec<String> = self.statements.iter().map(Node::string).collect();
+        format!("{{ {} }}", statements.join(" "))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expression<'a> {
     Identifier {
-        token: Token,
-        value: String,
+        token: Token<'a>,
+        value: Cow<'a, str>,
     },
     IntegerLiteral {
-        token: Token,
+        token: Token<'a>,
         value: i32,
     },
+    Boolean {
+        token: Token<'a>,
+        value: bool,
+    },
+    /// `value` is the decoded contents, escapes already resolved.
+    StringLiteral {
+        token: Token<'a>,
+        value: Cow<'a, str>,
+    },
+    ArrayLiteral {
+        token: Token<'a>,
+        elements: Vec<Expression<'a>>,
+    },
+    /// Pairs are kept in source order.
+    HashLiteral {
+        token: Token<'a>,
+        pairs: Vec<(Expression<'a>, Expression<'a>)>,
+    },
     Prefix {
-        token: Token,
-        operator: String,
-        right: Box<Expression>,
+        token: Token<'a>,
+        operator: Cow<'a, str>,
+        right: Box<Expression<'a>>,
     },
     Infix {
-        token: Token,
-        operator: String,
-        left: Box<Expression>,
-        right: Box<Expression>,
+        token: Token<'a>,
+        operator: Cow<'a, str>,
+        left: Box<Expression<'a>>,
+        right: Box<Expression<'a>>,
     },
+    /// The blocks are boxed to keep expressions small, which keeps down the
+    /// stack the recursive passes over the AST take per level.
+    If {
+        token: Token<'a>,
+        condition: Box<Expression<'a>>,
+        consequence: Box<BlockStatement<'a>>,
+        alternative: Option<Box<BlockStatement<'a>>>,
+    },
+    /// Parameters and body are reference counted so that every function value
+    /// created from the literal at runtime can share them.
+    FunctionLiteral {
+        token: Token<'a>,
+        parameters: Rc<[Token<'a>]>,
+        body: Rc<BlockStatement<'a>>,
+    },
+    Call {
+        token: Token<'a>,
+        function: Box<Expression<'a>>,
+        arguments: Vec<Expression<'a>>,
+    },
+    Index {
+        token: Token<'a>,
+        left: Box<Expression<'a>>,
+        index: Box<Expression<'a>>,
+    },
+}
+
+impl<'a> Expression<'a> {
+    /// The token the expression was parsed from: the operator of an infix
+    /// expression, the `(` of a call, the `[` of an index, otherwise its first
+    /// token.
+    pub fn token(&self) -> &Token<'a> {
+        use Expression::*;
+        match self {
+            Identifier { token, .. }
+            | IntegerLiteral { token, .. }
+            | Boolean { token, .. }
+            | StringLiteral { token, .. }
+            | ArrayLiteral { token, .. }
+            | HashLiteral { token, .. }
+            | Prefix { token, .. }
+            | Infix { token, .. }
+            | If { token, .. }
+            | FunctionLiteral { token, .. }
+            | Call { token, .. }
+            | Index { token, .. } => token,
+        }
+    }
+
+    pub fn into_owned(self) -> Expression<'static> {
+        use Expression::*;
+        match self {
+            Identifier { token, value } => Identifier {
+                token: token.into_owned(),
+                value: owned(value),
+            },
+            IntegerLiteral { token, value } => IntegerLiteral {
+                token: token.into_owned(),
+                value,
+            },
+            Boolean { token, value } => Boolean {
+                token: token.into_owned(),
+                value,
+            },
+            StringLiteral { token, value } => StringLiteral {
+                token: token.into_owned(),
+                value: owned(value),
+            },
+            ArrayLiteral { token, elements } => ArrayLiteral {
+                token: token.into_owned(),
+                elements: elements.into_iter().map(Expression::into_owned).collect(),
+            },
+            HashLiteral { token, pairs } => HashLiteral {
+                token: token.into_owned(),
+                pairs: pairs
+                    .into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect(),
+            },
+            Prefix {
+                token,
+                operator,
+                right,
+            } => Prefix {
+                token: token.into_owned(),
+                operator: owned(operator),
+                right: Box::new(right.into_owned()),
+            },
+            Infix {
+                token,
+                operator,
+                left,
+                right,
+            } => Infix {
+                token: token.into_owned(),
+                operator: owned(operator),
+                left: Box::new(left.into_owned()),
+                right: Box::new(right.into_owned()),
+            },
+            If {
+                token,
+                condition,
+                consequence,
+                alternative,
+            } => If {
+                token: token.into_owned(),
+                condition: Box::new(condition.into_owned()),
+                consequence: Box::new(consequence.into_owned()),
+                alternative: alternative.map(|alternative| Box::new(alternative.into_owned())),
+            },
+            FunctionLiteral {
+                token,
+                parameters,
+                body,
+            } => FunctionLiteral {
+                token: token.into_owned(),
+                parameters: parameters.iter().cloned().map(Token::into_owned).collect(),
+                body: Rc::new(body.as_ref().clone().into_owned()),
+            },
+            Call {
+                token,
+                function,
+                arguments,
+            } => Call {
+                token: token.into_owned(),
+                function: Box::new(function.into_owned()),
+                arguments: arguments.into_iter().map(Expression::into_owned).collect(),
+            },
+            Index { token, left, index } => Index {
+                token: token.into_owned(),
+                left: Box::new(left.into_owned()),
+                index: Box::new(index.into_owned()),
+            },
+        }
+    }
 }
 
-impl Node for Expression {
+impl Node for Expression<'_> {
     fn string(&self) -> String {
         use Expression::*;
         match self {
-            Identifier { ref value, .. } => value.clone(),
-            IntegerLiteral { ref token, .. } => token.literal.clone(),
+            Identifier { ref value, .. } => value.to_string(),
+            IntegerLiteral { ref token, .. } => token.literal.to_string(),
+            Boolean { ref token, .. } => token.literal.to_string(),
+            StringLiteral { ref value, .. } => quote(value),
+            ArrayLiteral { ref elements, .. } => {
+                let elements: Vec<String> = elements.iter().map(Node::string).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            HashLiteral { ref pairs, .. } => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key.string(), value.string()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
             Prefix {
                 ref operator,
                 ref right,
@@ -100,6 +321,69 @@ impl Node for Expression {
                 ref right,
                 ..
             } => format!("({} {} {})", left.string(), operator, right.string()),
+            If {
+                ref condition,
+                ref consequence,
+                ref alternative,
+                ..
+            } => match alternative {
+                Some(alternative) => format!(
+                    "if ({}) {} else {}",
+                    condition.string(),
+                    consequence.string(),
+                    alternative.string()
+                ),
+                None => format!("if ({}) {}", condition.string(), consequence.string()),
+            },
+            FunctionLiteral {
+                ref parameters,
+                ref body,
+                ..
+            } => {
+                let parameters: Vec<&str> = parameters.iter().map(|p| p.literal.as_ref()).collect();
+                format!("fn({}) {}", parameters.join(", "), body.string())
+            }
+            Call {
+                ref function,
+                ref arguments,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(Node::string).collect();
+                format!("{}({})", function.string(), arguments.join(", "))
+            }
+            Index {
+                ref left,
+                ref index,
+                ..
+            } => format!("({}[{}])", left.string(), index.string()),
+        }
+    }
+}
+
+fn owned_statements(statements: Vec<Statement>) -> Vec<Statement<'static>> {
+    statements.into_iter().map(Statement::into_owned).collect()
+}
+
+fn owned(value: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(value.into_owned())
+}
+
+/// Writes `value` back as a string literal the lexer reads as the same string.
+pub fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            ch if ch.is_control() => out.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => out.push(ch),
         }
     }
+    out.push('"');
+    out
 }
@@ -0,0 +1,714 @@
+//! The data model behind the [JSON](super::json) and
+//! [S-expression](super::sexpr) forms of the AST.
+//!
+//! Every node becomes a map whose first entry, `type`, names the node:
+//!
+//! | `type`                | other entries                                   |
+//! |-----------------------|-------------------------------------------------|
+//! | `Program`             | `statements`                                    |
+//! | `LetStatement`        | `token`, `name` (a token), `value`              |
+//! | `ReturnStatement`     | `token`, `value`                                |
+//! | `ExpressionStatement` | `token`, `expression`                           |
+//! | `Block`               | `token`, `statements`                           |
+//! | `Identifier`          | `token`, `value` (a string)                     |
+//! | `IntegerLiteral`      | `token`, `value` (an integer)                   |
+//! | `Boolean`             | `token`, `value` (a boolean)                    |
+//! | `StringLiteral`       | `token`, `value` (the decoded contents)         |
+//! | `ArrayLiteral`        | `token`, `elements`                             |
+//! | `HashLiteral`         | `token`, `pairs` (lists of a key and a value)   |
+//! | `Prefix`              | `token`, `operator`, `right`                    |
+//! | `Infix`               | `token`, `operator`, `left`, `right`            |
+//! | `If`                  | `token`, `condition`, `consequence`, `alternative` (only if there is one) |
+//! | `FunctionLiteral`     | `token`, `parameters` (tokens), `body`          |
+//! | `Call`                | `token`, `function`, `arguments`                |
+//! | `Index`               | `token`, `left`, `index`                        |
+//!
+//! A token is a map too, its `type` the [`TokenType`] variant, with a
+//! `literal`, a `span` of `start`, `end`, `line` and `column`, and `comments`
+//! (each a `text` and a `span`) if it has any.
+//!
+//! Entries come in the order above. Decoding ignores entries it doesn't know.
+
+use super::{BlockStatement, Expression, Program, Statement};
+use crate::token::{Comment, Span, Token, TokenType};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    List(Vec<Value>),
+    /// Entries are kept in order.
+    Map(Vec<(String, Value)>),
+}
+
+/// Why a serialized AST couldn't be read back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub message: String,
+    /// Where in the text the error is, for syntax errors.
+    pub span: Option<Span>,
+}
+
+impl DecodeError {
+    pub fn new<S: Into<String>>(message: S) -> DecodeError {
+        DecodeError {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// A syntax error at byte `offset` of `source`.
+    pub(crate) fn at<S: Into<String>>(message: S, source: &str, offset: usize) -> DecodeError {
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        DecodeError {
+            message: message.into(),
+            span: Some(Span {
+                start: offset,
+                end: offset,
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} at {}", self.message, span),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self) -> Value;
+}
+
+/// Decoded nodes own their text, so they outlive what they were read from.
+pub trait Decode: Sized {
+    fn decode(value: &Value) -> Result<Self, DecodeError>;
+}
+
+impl Encode for Program<'_> {
+    fn encode(&self) -> Value {
+        node("Program", vec![("statements", list(&self.statements))])
+    }
+}
+
+impl Decode for Program<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(value, &["Program"])?;
+        Ok(Program {
+            statements: map.decode_list("statements")?,
+        })
+    }
+}
+
+impl Encode for Statement<'_> {
+    fn encode(&self) -> Value {
+        match self {
+            Statement::Let { token, name, value } => node(
+                "LetStatement",
+                vec![
+                    ("token", token.encode()),
+                    ("name", name.encode()),
+                    ("value", value.encode()),
+                ],
+            ),
+            Statement::Return { token, value } => node(
+                "ReturnStatement",
+                vec![("token", token.encode()), ("value", value.encode())],
+            ),
+            Statement::Expression { token, expression } => node(
+                "ExpressionStatement",
+                vec![
+                    ("token", token.encode()),
+                    ("expression", expression.encode()),
+                ],
+            ),
+        }
+    }
+}
+
+impl Decode for Statement<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(
+            value,
+            &["LetStatement", "ReturnStatement", "ExpressionStatement"],
+        )?;
+        let token = map.decode("token")?;
+        Ok(match map.kind {
+            "LetStatement" => Statement::Let {
+                token,
+                name: map.decode("name")?,
+                value: map.decode("value")?,
+            },
+            "ReturnStatement" => Statement::Return {
+                token,
+                value: map.decode("value")?,
+            },
+            _ => Statement::Expression {
+                token,
+                expression: map.decode("expression")?,
+            },
+        })
+    }
+}
+
+impl Encode for BlockStatement<'_> {
+    fn encode(&self) -> Value {
+        node(
+            "Block",
+            vec![
+                ("token", self.token.encode()),
+                ("statements", list(&self.statements)),
+            ],
+        )
+    }
+}
+
+impl Decode for BlockStatement<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(value, &["Block"])?;
+        Ok(BlockStatement {
+            token: map.decode("token")?,
+            statements: map.decode_list("statements")?,
+        })
+    }
+}
+
+impl Encode for Expression<'_> {
+    fn encode(&self) -> Value {
+        match self {
+            Expression::Identifier { token, value } => node(
+                "Identifier",
+                vec![
+                    ("token", token.encode()),
+                    ("value", Value::String(value.to_string())),
+                ],
+            ),
+            Expression::IntegerLiteral { token, value } => node(
+                "IntegerLiteral",
+                vec![
+                    ("token", token.encode()),
+                    ("value", Value::Int(i64::from(*value))),
+                ],
+            ),
+            Expression::Boolean { token, value } => node(
+                "Boolean",
+                vec![("token", token.encode()), ("value", Value::Bool(*value))],
+            ),
+            Expression::StringLiteral { token, value } => node(
+                "StringLiteral",
+                vec![
+                    ("token", token.encode()),
+                    ("value", Value::String(value.to_string())),
+                ],
+            ),
+            Expression::ArrayLiteral { token, elements } => node(
+                "ArrayLiteral",
+                vec![("token", token.encode()), ("elements", list(elements))],
+            ),
+            Expression::HashLiteral { token, pairs } => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| Value::List(vec![key.encode(), value.encode()]))
+                    .collect();
+                node(
+                    "HashLiteral",
+                    vec![("token", token.encode()), ("pairs", Value::List(pairs))],
+                )
+            }
+            Expression::Prefix {
+                token,
+                operator,
+                right,
+            } => node(
+                "Prefix",
+                vec![
+                    ("token", token.encode()),
+                    ("operator", Value::String(operator.to_string())),
+                    ("right", right.encode()),
+                ],
+            ),
+            Expression::Infix {
+                token,
+                operator,
+                left,
+                right,
+            } => node(
+                "Infix",
+                vec![
+                    ("token", token.encode()),
+                    ("operator", Value::String(operator.to_string())),
+                    ("left", left.encode()),
+                    ("right", right.encode()),
+                ],
+            ),
+            Expression::If {
+                token,
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let mut entries = vec![
+                    ("token", token.encode()),
+                    ("condition", condition.encode()),
+                    ("consequence", consequence.encode()),
+                ];
+                if let Some(alternative) = alternative {
+                    entries.push(("alternative", alternative.encode()));
+                }
+                node("If", entries)
+            }
+            Expression::FunctionLiteral {
+                token,
+                parameters,
+                body,
+            } => node(
+                "FunctionLiteral",
+                vec![
+                    ("token", token.encode()),
+                    ("parameters", list(parameters)),
+                    ("body", body.encode()),
+                ],
+            ),
+            Expression::Call {
+                token,
+                function,
+                arguments,
+            } => node(
+                "Call",
+                vec![
+                    ("token", token.encode()),
+                    ("function", function.encode()),
+                    ("arguments", list(arguments)),
+                ],
+            ),
+            Expression::Index { token, left, index } => node(
+                "Index",
+                vec![
+                    ("token", token.encode()),
+                    ("left", left.encode()),
+                    ("index", index.encode()),
+                ],
+            ),
+        }
+    }
+}
+
+impl Decode for Expression<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(
+            value,
+            &[
+                "Identifier",
+                "IntegerLiteral",
+                "Boolean",
+                "StringLiteral",
+                "ArrayLiteral",
+                "HashLiteral",
+                "Prefix",
+                "Infix",
+                "If",
+                "FunctionLiteral",
+                "Call",
+                "Index",
+            ],
+        )?;
+        let token = map.decode("token")?;
+        Ok(match map.kind {
+            "Identifier" => Expression::Identifier {
+                token,
+                value: Cow::Owned(map.string("value")?.to_string()),
+            },
+            "IntegerLiteral" => Expression::IntegerLiteral {
+                token,
+                value: map.int("value")?,
+            },
+            "Boolean" => Expression::Boolean {
+                token,
+                value: match map.get("value")? {
+                    Value::Bool(value) => *value,
+                    _ => return Err(map.mistyped("value", "a boolean")),
+                },
+            },
+            "StringLiteral" => Expression::StringLiteral {
+                token,
+                value: Cow::Owned(map.string("value")?.to_string()),
+            },
+            "ArrayLiteral" => Expression::ArrayLiteral {
+                token,
+                elements: map.decode_list("elements")?,
+            },
+            "HashLiteral" => {
+                let mut pairs = Vec::new();
+                for pair in map.list("pairs")? {
+                    match pair {
+                        Value::List(pair) if pair.len() == 2 => pairs
+                            .push((Expression::decode(&pair[0])?, Expression::decode(&pair[1])?)),
+                        _ => return Err(map.mistyped("pairs", "a list of pairs")),
+                    }
+                }
+                Expression::HashLiteral { token, pairs }
+            }
+            "Prefix" => Expression::Prefix {
+                token,
+                operator: Cow::Owned(map.string("operator")?.to_string()),
+                right: Box::new(map.decode("right")?),
+            },
+            "Infix" => Expression::Infix {
+                token,
+                operator: Cow::Owned(map.string("operator")?.to_string()),
+                left: Box::new(map.decode("left")?),
+                right: Box::new(map.decode("right")?),
+            },
+            "If" => Expression::If {
+                token,
+                condition: Box::new(map.decode("condition")?),
+                consequence: Box::new(map.decode("consequence")?),
+                alternative: match map.find("alternative") {
+                    Some(alternative) => Some(Box::new(BlockStatement::decode(alternative)?)),
+                    None => None,
+                },
+            },
+            "FunctionLiteral" => Expression::FunctionLiteral {
+                token,
+                parameters: Rc::from(map.decode_list::<Token>("parameters")?),
+                body: Rc::new(map.decode("body")?),
+            },
+            "Call" => Expression::Call {
+                token,
+                function: Box::new(map.decode("function")?),
+                arguments: map.decode_list("arguments")?,
+            },
+            _ => Expression::Index {
+                token,
+                left: Box::new(map.decode("left")?),
+                index: Box::new(map.decode("index")?),
+            },
+        })
+    }
+}
+
+impl Encode for Token<'_> {
+    fn encode(&self) -> Value {
+        let mut entries = vec![
+            ("literal", Value::String(self.literal.to_string())),
+            ("span", self.span.encode()),
+        ];
+        if !self.comments.is_empty() {
+            entries.push(("comments", list(&self.comments)));
+        }
+        node(&format!("{:?}", self.t), entries)
+    }
+}
+
+impl Decode for Token<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(value, &[])?;
+        let name = map.string("type")?;
+        let t = TOKEN_TYPES
+            .iter()
+            .copied()
+            .find(|t| format!("{:?}", t) == name)
+            .ok_or_else(|| DecodeError::new(format!("unknown token type {}", name)))?;
+        Ok(Token {
+            t,
+            literal: Cow::Owned(map.string("literal")?.to_string()),
+            span: map.decode("span")?,
+            comments: match map.find("comments") {
+                Some(_) => map.decode_list("comments")?,
+                None => Vec::new(),
+            },
+        })
+    }
+}
+
+impl Encode for Comment<'_> {
+    fn encode(&self) -> Value {
+        Value::Map(vec![
+            ("text".to_string(), Value::String(self.text.to_string())),
+            ("span".to_string(), self.span.encode()),
+        ])
+    }
+}
+
+impl Decode for Comment<'static> {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(value, &[])?;
+        Ok(Comment {
+            text: Cow::Owned(map.string("text")?.to_string()),
+            span: map.decode("span")?,
+        })
+    }
+}
+
+impl Encode for Span {
+    fn encode(&self) -> Value {
+        let position = |n: usize| Value::Int(n as i64);
+        Value::Map(vec![
+            ("start".to_string(), position(self.start)),
+            ("end".to_string(), position(self.end)),
+            ("line".to_string(), position(self.line)),
+            ("column".to_string(), position(self.column)),
+        ])
+    }
+}
+
+impl Decode for Span {
+    fn decode(value: &Value) -> Result<Self, DecodeError> {
+        let map = Map::of(value, &[])?;
+        Ok(Span {
+            start: map.int("start")?,
+            end: map.int("end")?,
+            line: map.int("line")?,
+            column: map.int("column")?,
+        })
+    }
+}
+
+/// Every token type, to find one by name.
+const TOKEN_TYPES: &[TokenType] = &[
+    TokenType::Illegal,
+    TokenType::UnterminatedString,
+    TokenType::InvalidEscape,
+    TokenType::UnterminatedComment,
+    TokenType::EOF,
+    TokenType::Ident,
+    TokenType::Int,
+    TokenType::String,
+    TokenType::Assign,
+    TokenType::Plus,
+    TokenType::Minus,
+    TokenType::Bang,
+    TokenType::Asterisk,
+    TokenType::Slash,
+    TokenType::LT,
+    TokenType::GT,
+    TokenType::Eq,
+    TokenType::NotEq,
+    TokenType::Comma,
+    TokenType::Semicolon,
+    TokenType::Colon,
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Function,
+    TokenType::Let,
+    TokenType::True,
+    TokenType::False,
+    TokenType::If,
+    TokenType::Else,
+    TokenType::Return,
+];
+
+fn node(kind: &str, entries: Vec<(&str, Value)>) -> Value {
+    let mut map = vec![("type".to_string(), Value::String(kind.to_string()))];
+    map.extend(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    Value::Map(map)
+}
+
+fn list<T: Encode>(items: &[T]) -> Value {
+    Value::List(items.iter().map(Encode::encode).collect())
+}
+
+/// A map being decoded. `kind` is its `type`, or empty for maps without one.
+struct Map<'v> {
+    kind: &'v str,
+    entries: &'v [(String, Value)],
+}
+
+impl<'v> Map<'v> {
+    /// `value` as a map, whose `type` has to be one of `kinds` unless there are
+    /// none.
+    fn of(value: &'v Value, kinds: &[&str]) -> Result<Map<'v>, DecodeError> {
+        let entries = match value {
+            Value::Map(entries) => entries,
+            _ => return Err(DecodeError::new("expected a map")),
+        };
+        let mut map = Map { kind: "", entries };
+        if kinds.is_empty() {
+            return Ok(map);
+        }
+        map.kind = map.string("type")?;
+        if !kinds.contains(&map.kind) {
+            return Err(DecodeError::new(format!(
+                "unexpected type {}, expected {}",
+                map.kind,
+                kinds.join(" or ")
+            )));
+        }
+        Ok(map)
+    }
+
+    fn find(&self, key: &str) -> Option<&'v Value> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    fn get(&self, key: &str) -> Result<&'v Value, DecodeError> {
+        self.find(key).ok_or_else(|| {
+            DecodeError::new(match self.kind {
+                "" => format!("missing {}", key),
+                kind => format!("missing {} in {}", key, kind),
+            })
+        })
+    }
+
+    fn mistyped(&self, key: &str, expected: &str) -> DecodeError {
+        DecodeError::new(match self.kind {
+            "" => format!("{} should be {}", key, expected),
+            kind => format!("{} in {} should be {}", key, kind, expected),
+        })
+    }
+
+    fn string(&self, key: &str) -> Result<&'v str, DecodeError> {
+        match self.get(key)? {
+            Value::String(s) => Ok(s),
+            _ => Err(self.mistyped(key, "a string")),
+        }
+    }
+
+    fn int<N: TryFrom<i64>>(&self, key: &str) -> Result<N, DecodeError> {
+        match self.get(key)? {
+            Value::Int(n) => N::try_from(*n).map_err(|_| self.mistyped(key, "in range")),
+            _ => Err(self.mistyped(key, "an integer")),
+        }
+    }
+
+    fn list(&self, key: &str) -> Result<&'v [Value], DecodeError> {
+        match self.get(key)? {
+            Value::List(items) => Ok(items),
+            _ => Err(self.mistyped(key, "a list")),
+        }
+    }
+
+    fn decode<T: Decode>(&self, key: &str) -> Result<T, DecodeError> {
+        T::decode(self.get(key)?)
+    }
+
+    fn decode_list<T: Decode>(&self, key: &str) -> Result<Vec<T>, DecodeError> {
+        self.list(key)?.iter().map(T::decode).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Node;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+    use crate::parser::Parser;
+
+    #[test]
+    fn round_trip() {
+        let source = "let f = fn(a, b) { if (!a) { return b[0]; } else { a * -b } };\n\
+                      f(true, [1, 2]); {\"k\\n\": 3, x: \"y\"}; f(1)(2);";
+        let program = parse(source).unwrap();
+        let encoded = program.encode();
+        let decoded = Program::decode(&encoded).unwrap();
+        assert_eq!(decoded.string(), program.string());
+        assert_eq!(decoded.encode(), encoded);
+    }
+
+    #[test]
+    fn tokens() {
+        let mut parser = Parser::new(Lexer::with_comments("// hi\nx"));
+        let program = parser.parse_program();
+        let token = match &program.statements[0] {
+            Statement::Expression { token, .. } => token,
+            _ => unreachable!(),
+        };
+        let encoded = token.encode();
+        assert_eq!(
+            encoded,
+            node(
+                "Ident",
+                vec![
+                    ("literal", Value::String("x".to_string())),
+                    (
+                        "span",
+                        Span {
+                            start: 6,
+                            end: 7,
+                            line: 2,
+                            column: 1,
+                        }
+                        .encode()
+                    ),
+                    (
+                        "comments",
+                        Value::List(vec![Value::Map(vec![
+                            ("text".to_string(), Value::String("// hi".to_string())),
+                            (
+                                "span".to_string(),
+                                Span {
+                                    start: 0,
+                                    end: 5,
+                                    line: 1,
+                                    column: 1,
+                                }
+                                .encode()
+                            ),
+                        ])]),
+                    ),
+                ]
+            )
+        );
+        assert_eq!(&Token::decode(&encoded).unwrap(), token);
+    }
+
+    #[test]
+    fn errors() {
+        let span = Span::default().encode();
+        let token = node(
+            "Int",
+            vec![("literal", Value::String("1".to_string())), ("span", span)],
+        );
+        let tests = vec![
+            (Value::Int(1), "expected a map"),
+            (node("Nothing", vec![]), "unexpected type Nothing, expected Identifier or IntegerLiteral or Boolean or StringLiteral or ArrayLiteral or HashLiteral or Prefix or Infix or If or FunctionLiteral or Call or Index"),
+            (node("IntegerLiteral", vec![]), "missing token in IntegerLiteral"),
+            (
+                node("IntegerLiteral", vec![("token", token.clone())]),
+                "missing value in IntegerLiteral",
+            ),
+            (
+                node(
+                    "IntegerLiteral",
+                    vec![("token", token.clone()), ("value", Value::Int(1 << 40))],
+                ),
+                "value in IntegerLiteral should be in range",
+            ),
+            (
+                node(
+                    "Boolean",
+                    vec![("token", token), ("value", Value::Int(1))],
+                ),
+                "value in Boolean should be a boolean",
+            ),
+        ];
+
+        for (value, expected) in tests {
+            let error = Expression::decode(&value).unwrap_err();
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! The AST as S-expressions, in the shape described in
+//! [`serialize`](super::serialize).
+//!
+//! A node is written `(Type :key value ...)`, and any other map, like a span,
+//! `(:key value ...)`. Lists are `(item ...)`, strings are quoted the way
+//! Monkey quotes them, and booleans are `#t` and `#f`. `;` starts a comment
+//! that runs to the end of the line.
+//!
+//! ```
+//! use monkey_rs::ast::{sexpr, Expression};
+//!
+//! let text = "(Identifier
+//!   :token (Ident :literal \"x\" :span (:start 0 :end 1 :line 1 :column 1))
+//!   :value \"x\")";
+//! let identifier: Expression = sexpr::from_str(text).unwrap();
+//! assert_eq!(sexpr::to_string(&identifier), format!("{}\n", text));
+//! ```
+
+use super::quote;
+use super::serialize::{Decode, DecodeError, Encode, Value};
+
+/// Lines are broken to keep them within this many bytes, where possible.
+const WIDTH: usize = 80;
+const MAX_DEPTH: usize = 512;
+
+/// `node` as an indented S-expression, ending with a newline.
+pub fn to_string<T: Encode + ?Sized>(node: &T) -> String {
+    let mut out = String::new();
+    write(&node.encode(), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+pub fn from_str<T: Decode>(source: &str) -> Result<T, DecodeError> {
+    T::decode(&read(source)?)
+}
+
+/// Writes `value` on what's left of the line if it fits, and with one item or
+/// entry per line otherwise.
+pub fn write(value: &Value, indent: usize, out: &mut String) {
+    // NOTE: giving up on the one-line form once it's too long keeps this
+    //       linear; building it whole at every level would be quadratic
+    let column = out.len() - out.rfind('\n').map_or(0, |i| i + 1);
+    let mut line = String::new();
+    if compact(value, WIDTH.saturating_sub(column), &mut line) {
+        out.push_str(&line);
+        return;
+    }
+
+    match value {
+        Value::List(items) => {
+            out.push('(');
+            for item in items {
+                newline(indent + 1, out);
+                write(item, indent + 1, out);
+            }
+            out.push(')');
+        }
+        Value::Map(entries) => {
+            out.push('(');
+            let entries = match head(entries) {
+                Some(kind) => {
+                    out.push_str(kind);
+                    &entries[1..]
+                }
+                None => entries,
+            };
+            for (key, value) in entries {
+                newline(indent + 1, out);
+                out.push(':');
+                out.push_str(key);
+                out.push(' ');
+                write(value, indent + 1, out);
+            }
+            out.push(')');
+        }
+        _ => {
+            compact(value, usize::MAX, out);
+        }
+    }
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&"  ".repeat(indent));
+}
+
+/// Writes `value` on one line, as long as that takes at most `limit` bytes.
+/// Returns whether it did; past the limit, what was written is cut short.
+fn compact(value: &Value, limit: usize, out: &mut String) -> bool {
+    let end = out.len().saturating_add(limit);
+    match value {
+        Value::Bool(true) => out.push_str("#t"),
+        Value::Bool(false) => out.push_str("#f"),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        // NOTE: quoting never makes a string shorter
+        Value::String(s) if out.len() + s.len() + 2 > end => return false,
+        Value::String(s) => out.push_str(&quote(s)),
+        Value::List(items) => {
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                if !compact(item, end.saturating_sub(out.len()), out) {
+                    return false;
+                }
+            }
+            out.push(')');
+        }
+        Value::Map(entries) => {
+            out.push('(');
+            let open = out.len();
+            let entries = match head(entries) {
+                Some(kind) => {
+                    out.push_str(kind);
+                    &entries[1..]
+                }
+                None => entries,
+            };
+            for (key, value) in entries {
+                if out.len() > open {
+                    out.push(' ');
+                }
+                out.push(':');
+                out.push_str(key);
+                out.push(' ');
+                if !compact(value, end.saturating_sub(out.len()), out) {
+                    return false;
+                }
+            }
+            out.push(')');
+        }
+    }
+    out.len() <= end
+}
+
+/// The type a map is written with at its head, if it has one.
+fn head(entries: &[(String, Value)]) -> Option<&str> {
+    match entries.first() {
+        Some((key, Value::String(kind))) if key == "type" && is_symbol(kind) => Some(kind),
+        _ => None,
+    }
+}
+
+fn is_symbol(s: &str) -> bool {
+    s.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+        && s.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+pub fn read(source: &str) -> Result<Value, DecodeError> {
+    let mut reader = Reader {
+        source,
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < source.len() {
+        return Err(reader.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+struct Reader<'s> {
+    source: &'s str,
+    position: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: &str) -> DecodeError {
+        DecodeError::at(message, self.source, self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += ch.len_utf8();
+        Some(ch)
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\n' | '\r') => self.position += 1,
+                Some(';') => {
+                    let rest = &self.source[self.position..];
+                    self.position += rest.find('\n').unwrap_or(rest.len());
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Reads a run of symbol characters.
+    fn symbol(&mut self) -> &str {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            self.position += 1;
+        }
+        &self.source[start..self.position]
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        self.skip_whitespace();
+        let start = self.position;
+        match self.peek() {
+            Some('(') => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(self.error("nested too deeply"));
+                }
+                self.depth += 1;
+                self.position += 1;
+                let value = self.list();
+                self.depth -= 1;
+                value
+            }
+            Some('"') => self.string().map(Value::String),
+            Some('-') | Some('0'..='9') => {
+                self.position += 1;
+                while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                let text = &self.source[start..self.position];
+                text.parse().map(Value::Int).map_err(|_| {
+                    self.position = start;
+                    self.error("invalid integer")
+                })
+            }
+            Some('#') => {
+                self.position += 1;
+                match self.symbol() {
+                    "t" => Ok(Value::Bool(true)),
+                    "f" => Ok(Value::Bool(false)),
+                    _ => {
+                        self.position = start;
+                        Err(self.error("expected #t or #f"))
+                    }
+                }
+            }
+            Some(')') => Err(self.error("unexpected )")),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Reads what follows a `(`: a map if it starts with a type or a key, and
+    /// a list otherwise.
+    fn list(&mut self) -> Result<Value, DecodeError> {
+        self.skip_whitespace();
+        let mut entries = Vec::new();
+        match self.peek() {
+            Some(':') => {}
+            Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {
+                let kind = self.symbol().to_string();
+                entries.push(("type".to_string(), Value::String(kind)));
+            }
+            _ => {
+                let mut items = Vec::new();
+                while !self.eat(')') {
+                    items.push(self.value()?);
+                }
+                return Ok(Value::List(items));
+            }
+        }
+
+        while !self.eat(')') {
+            match self.peek() {
+                Some(':') => {}
+                Some(_) => return Err(self.error("expected a key")),
+                None => return Err(self.error("unexpected end of input")),
+            }
+            self.position += 1;
+            let key = self.symbol().to_string();
+            if key.is_empty() {
+                return Err(self.error("expected a key"));
+            }
+            entries.push((key, self.value()?));
+        }
+        Ok(Value::Map(entries))
+    }
+
+    /// Skips whitespace and `expected`, if that's what comes next.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.position += 1;
+        let mut out = String::new();
+        loop {
+            let start = self.position;
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let ch = match self.next() {
+                        Some('"') => Some('"'),
+                        Some('\\') => Some('\\'),
+                        Some('n') => Some('\n'),
+                        Some('t') => Some('\t'),
+                        Some('r') => Some('\r'),
+                        Some('0') => Some('\0'),
+                        Some('u') => self.unicode_escape(),
+                        _ => None,
+                    };
+                    match ch {
+                        Some(ch) => out.push(ch),
+                        None => {
+                            self.position = start;
+                            return Err(self.error("invalid escape"));
+                        }
+                    }
+                }
+                Some(ch) => out.push(ch),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The character of a `\u{...}` escape whose `\u` has been read.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let rest = self.source[self.position..].strip_prefix('{')?;
+        let digits = &rest[..rest.find('}')?];
+        let ch = char::from_u32(u32::from_str_radix(digits, 16).ok()?)?;
+        self.position += digits.len() + 2;
+        Some(ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Program;
+    use crate::parser::parse;
+
+    #[test]
+    fn output() {
+        let program = parse("f(x, \"a\\n\");").unwrap();
+        assert_eq!(
+            to_string(&program),
+            r#"(Program
+  :statements (
+    (ExpressionStatement
+      :token (Ident :literal "f" :span (:start 0 :end 1 :line 1 :column 1))
+      :expression (Call
+        :token (LeftParen
+          :literal "("
+          :span (:start 1 :end 2 :line 1 :column 2))
+        :function (Identifier
+          :token (Ident :literal "f" :span (:start 0 :end 1 :line 1 :column 1))
+          :value "f")
+        :arguments (
+          (Identifier
+            :token (Ident
+              :literal "x"
+              :span (:start 2 :end 3 :line 1 :column 3))
+            :value "x")
+          (StringLiteral
+            :token (String
+              :literal "a\n"
+              :span (:start 5 :end 10 :line 1 :column 6))
+            :value "a\n"))))))
+"#
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = "let s = \"tab\\t \\\"quote\\\" \u{e9} \\0\";\n\
+                      if (s) { fn(a) { a[-1] } } else { {1: [], \"k\": {}, true: !false} }";
+        let program = parse(source).unwrap();
+        let text = to_string(&program);
+        let decoded: Program = from_str(&text).unwrap();
+        assert_eq!(to_string(&decoded), text);
+    }
+
+    #[test]
+    fn values() {
+        let tests = vec![
+            (" #t ; comment", Value::Bool(true)),
+            ("-42", Value::Int(-42)),
+            (r#""a\u{e9}\n""#, Value::String("a\u{e9}\n".to_string())),
+            ("( )", Value::List(vec![])),
+            (
+                "(Pair :a (1 #f) ; first\n :b (:c \"d\"))",
+                Value::Map(vec![
+                    ("type".to_string(), Value::String("Pair".to_string())),
+                    (
+                        "a".to_string(),
+                        Value::List(vec![Value::Int(1), Value::Bool(false)]),
+                    ),
+                    (
+                        "b".to_string(),
+                        Value::Map(vec![("c".to_string(), Value::String("d".to_string()))]),
+                    ),
+                ]),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(read(input), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let tests = vec![
+            ("", "unexpected end of input at 1:1"),
+            ("(1\n 2", "unexpected end of input at 2:3"),
+            ("(Int 1)", "expected a key at 1:6"),
+            ("(:a)", "unexpected ) at 1:4"),
+            ("#x", "expected #t or #f at 1:1"),
+            ("99999999999999999999", "invalid integer at 1:1"),
+            ("x", "expected a value at 1:1"),
+            (r#""\u{110000}""#, "invalid escape at 1:2"),
+            ("\"abc", "unterminated string at 1:5"),
+            ("() ()", "unexpected text after the value at 1:4"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(read(input).unwrap_err().to_string(), expected, "{}", input);
+        }
+        assert_eq!(
+            read(&"(".repeat(MAX_DEPTH + 1)).unwrap_err().to_string(),
+            format!("nested too deeply at 1:{}", MAX_DEPTH + 1)
+        );
+        assert_eq!(
+            from_str::<Program>("(Program :statements ((Nope)))")
+                .unwrap_err()
+                .to_string(),
+            "unexpected type Nope, expected LetStatement or ReturnStatement or ExpressionStatement"
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! Traversals of the AST.
+//!
+//! Each trait has a method per kind of node, whose default goes on to the
+//! node's children through the matching `walk_*` (or `fold_*`) function. An
+//! implementation overrides the nodes it cares about, and calls the function
+//! itself if it still wants the children visited.
+
+use super::{BlockStatement, Expression, Program, Statement};
+use std::rc::Rc;
+
+/// Looks at every node.
+pub trait Visitor<'a> {
+    fn visit_program(&mut self, program: &Program<'a>) {
+        walk_program(self, program);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement<'a>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_block_statement(&mut self, block: &BlockStatement<'a>) {
+        walk_block_statement(self, block);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression<'a>) {
+        walk_expression(self, expression);
+    }
+}
+
+pub fn walk_program<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, program: &Program<'a>) {
+    for statement in &program.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, statement: &Statement<'a>) {
+    match statement {
+        Statement::Let { value, .. } | Statement::Return { value, .. } => {
+            visitor.visit_expression(value)
+        }
+        Statement::Expression { expression, .. } => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_block_statement<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    block: &BlockStatement<'a>,
+) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_expression<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expression: &Expression<'a>) {
+    match expression {
+        Expression::Identifier { .. }
+        | Expression::IntegerLiteral { .. }
+        | Expression::Boolean { .. }
+        | Expression::StringLiteral { .. } => {}
+        Expression::ArrayLiteral { elements, .. } => {
+            for element in elements {
+                visitor.visit_expression(element);
+            }
+        }
+        Expression::HashLiteral { pairs, .. } => {
+            for (key, value) in pairs {
+                visitor.visit_expression(key);
+                visitor.visit_expression(value);
+            }
+        }
+        Expression::Prefix { right, .. } => visitor.visit_expression(right),
+        Expression::Infix { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block_statement(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block_statement(alternative);
+            }
+        }
+        Expression::FunctionLiteral { body, .. } => visitor.visit_block_statement(body),
+        Expression::Call {
+            function,
+            arguments,
+            ..
+        } => {
+            visitor.visit_expression(function);
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        Expression::Index { left, index, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(index);
+        }
+    }
+}
+
+/// Changes nodes in place.
+///
+/// Function bodies are shared with the function values made from them, so
+/// visiting one that is shared copies it first.
+pub trait VisitorMut<'a> {
+    fn visit_program_mut(&mut self, program: &mut Program<'a>) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement<'a>) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_block_statement_mut(&mut self, block: &mut BlockStatement<'a>) {
+        walk_block_statement_mut(self, block);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression<'a>) {
+        walk_expression_mut(self, expression);
+    }
+}
+
+pub fn walk_program_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program<'a>,
+) {
+    for statement in &mut program.statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    statement: &mut Statement<'a>,
+) {
+    match statement {
+        Statement::Let { value, .. } | Statement::Return { value, .. } => {
+            visitor.visit_expression_mut(value)
+        }
+        Statement::Expression { expression, .. } => visitor.visit_expression_mut(expression),
+    }
+}
+
+pub fn walk_block_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    block: &mut BlockStatement<'a>,
+) {
+    for statement in &mut block.statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_expression_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    expression: &mut Expression<'a>,
+) {
+    match expression {
+        Expression::Identifier { .. }
+        | Expression::IntegerLiteral { .. }
+        | Expression::Boolean { .. }
+        | Expression::StringLiteral { .. } => {}
+        Expression::ArrayLiteral { elements, .. } => {
+            for element in elements {
+                visitor.visit_expression_mut(element);
+            }
+        }
+        Expression::HashLiteral { pairs, .. } => {
+            for (key, value) in pairs {
+                visitor.visit_expression_mut(key);
+                visitor.visit_expression_mut(value);
+            }
+        }
+        Expression::Prefix { right, .. } => visitor.visit_expression_mut(right),
+        Expression::Infix { left, right, .. } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_statement_mut(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block_statement_mut(alternative);
+            }
+        }
+        Expression::FunctionLiteral { body, .. } => {
+            visitor.visit_block_statement_mut(Rc::make_mut(body))
+        }
+        Expression::Call {
+            function,
+            arguments,
+            ..
+        } => {
+            visitor.visit_expression_mut(function);
+            for argument in arguments {
+                visitor.visit_expression_mut(argument);
+            }
+        }
+        Expression::Index { left, index, .. } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(index);
+        }
+    }
+}
+
+/// Rebuilds the tree, node by node, possibly replacing nodes with different
+/// ones.
+pub trait Fold<'a> {
+    fn fold_program(&mut self, program: Program<'a>) -> Program<'a> {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, statement: Statement<'a>) -> Statement<'a> {
+        fold_statement(self, statement)
+    }
+
+    fn fold_block_statement(&mut self, block: BlockStatement<'a>) -> BlockStatement<'a> {
+        fold_block_statement(self, block)
+    }
+
+    fn fold_expression(&mut self, expression: Expression<'a>) -> Expression<'a> {
+        fold_expression(self, expression)
+    }
+}
+
+pub fn fold_program<'a, F: Fold<'a> + ?Sized>(folder: &mut F, program: Program<'a>) -> Program<'a> {
+    Program {
+        statements: fold_statements(folder, program.statements),
+    }
+}
+
+pub fn fold_statement<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    statement: Statement<'a>,
+) -> Statement<'a> {
+    match statement {
+        Statement::Let { token, name, value } => Statement::Let {
+            token,
+            name,
+            value: folder.fold_expression(value),
+        },
+        Statement::Return { token, value } => Statement::Return {
+            token,
+            value: folder.fold_expression(value),
+        },
+        Statement::Expression { token, expression } => Statement::Expression {
+            token,
+            expression: folder.fold_expression(expression),
+        },
+    }
+}
+
+pub fn fold_block_statement<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    block: BlockStatement<'a>,
+) -> BlockStatement<'a> {
+    BlockStatement {
+        token: block.token,
+        statements: fold_statements(folder, block.statements),
+    }
+}
+
+pub fn fold_expression<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    expression: Expression<'a>,
+) -> Expression<'a> {
+    match expression {
+        Expression::Identifier { .. }
+        | Expression::IntegerLiteral { .. }
+        | Expression::Boolean { .. }
+        | Expression::StringLiteral { .. } => expression,
+        Expression::ArrayLiteral { token, elements } => Expression::ArrayLiteral {
+            token,
+            elements: fold_expressions(folder, elements),
+        },
+        Expression::HashLiteral { token, pairs } => Expression::HashLiteral {
+            token,
+            pairs: pairs
+                .into_iter()
+                .map(|(key, value)| (folder.fold_expression(key), folder.fold_expression(value)))
+                .collect(),
+        },
+        Expression::Prefix {
+            token,
+            operator,
+            right,
+        } => Expression::Prefix {
+            token,
+            operator,
+            right: Box::new(folder.fold_expression(*right)),
+        },
+        Expression::Infix {
+            token,
+            operator,
+            left,
+            right,
+        } => Expression::Infix {
+            token,
+            operator,
+            left: Box::new(folder.fold_expression(*left)),
+            right: Box::new(folder.fold_expression(*right)),
+        },
+        Expression::If {
+            token,
+            condition,
+            consequence,
+            alternative,
+        } => Expression::If {
+            token,
+            condition: Box::new(folder.fold_expression(*condition)),
+            consequence: Box::new(folder.fold_block_statement(*consequence)),
+            alternative: alternative
+                .map(|alternative| Box::new(folder.fold_block_statement(*alternative))),
+        },
+        Expression::FunctionLiteral {
+            token,
+            parameters,
+            body,
+        } => {
+            let body = Rc::try_unwrap(body).unwrap_or_else(|body| (*body).clone());
+            Expression::FunctionLiteral {
+                token,
+                parameters,
+                body: Rc::new(folder.fold_block_statement(body)),
+            }
+        }
+        Expression::Call {
+            token,
+            function,
+            arguments,
+        } => Expression::Call {
+            token,
+            function: Box::new(folder.fold_expression(*function)),
+            arguments: fold_expressions(folder, arguments),
+        },
+        Expression::Index { token, left, index } => Expression::Index {
+            token,
+            left: Box::new(folder.fold_expression(*left)),
+            index: Box::new(folder.fold_expression(*index)),
+        },
+    }
+}
+
+fn fold_statements<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    statements: Vec<Statement<'a>>,
+) -> Vec<Statement<'a>> {
+    statements
+        .into_iter()
+        .map(|statement| folder.fold_statement(statement))
+        .collect()
+}
+
+fn fold_expressions<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    expressions: Vec<Expression<'a>>,
+) -> Vec<Expression<'a>> {
+    expressions
+        .into_iter()
+        .map(|expression| folder.fold_expression(expression))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Node;
+    use crate::parser::parse;
+    use crate::token::Token;
+    use std::borrow::Cow;
+
+    /// Collects identifiers in the order they're visited.
+    struct Identifiers(Vec<String>);
+
+    impl<'a> Visitor<'a> for Identifiers {
+        fn visit_expression(&mut self, expression: &Expression<'a>) {
+            if let Expression::Identifier { value, .. } = expression {
+                self.0.push(value.to_string());
+            }
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        let program = parse(
+            "let f = fn(a) { if (a) { g(a[b]) } else { -c } }; \
+             [d, {e: h + i}]; return j;",
+        )
+        .unwrap();
+        let mut identifiers = Identifiers(Vec::new());
+        identifiers.visit_program(&program);
+        assert_eq!(
+            identifiers.0,
+            ["a", "g", "a", "b", "c", "d", "e", "h", "i", "j"]
+        );
+    }
+
+    /// Renames one variable everywhere.
+    struct Rename(&'static str, &'static str);
+
+    impl<'a> VisitorMut<'a> for Rename {
+        fn visit_expression_mut(&mut self, expression: &mut Expression<'a>) {
+            if let Expression::Identifier { value, .. } = expression {
+                if value == self.0 {
+                    *value = Cow::Borrowed(self.1);
+                }
+            }
+            walk_expression_mut(self, expression);
+        }
+    }
+
+    #[test]
+    fn visitor_mut() {
+        let mut program = parse("let y = fn(x) { x + 1 }(x); [x, y]").unwrap();
+        let shared = match &program.statements[0] {
+            Statement::Let {
+                value: Expression::Call { function, .. },
+                ..
+            } => match function.as_ref() {
+                Expression::FunctionLiteral { body, .. } => body.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        Rename("x", "z").visit_program_mut(&mut program);
+        assert_eq!(program.string(), "let y = fn(x) { (z + 1); }(z);[z, y];");
+        assert_eq!(shared.string(), "{ (x + 1); }");
+    }
+
+    /// Adds up integer literals wherever both sides of a `+` are one.
+    struct ConstantFolding;
+
+    impl<'a> Fold<'a> for ConstantFolding {
+        fn fold_expression(&mut self, expression: Expression<'a>) -> Expression<'a> {
+            match fold_expression(self, expression) {
+                Expression::Infix {
+                    token,
+                    operator,
+                    left,
+                    right,
+                } => match (*left, *right) {
+                    (
+                        Expression::IntegerLiteral { token, value: left },
+                        Expression::IntegerLiteral { value: right, .. },
+                    ) if operator == "+" => {
+                        let value = left.wrapping_add(right);
+                        Expression::IntegerLiteral {
+                            token: Token {
+                                literal: Cow::Owned(value.to_string()),
+                                ..token
+                            },
+                            value,
+                        }
+                    }
+                    (left, right) => Expression::Infix {
+                        token,
+                        operator,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                },
+                expression => expression,
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        let program = parse("let f = fn() { 1 + 2 + x }; f(3 + 4) * (5 + 6)").unwrap();
+        let folded = ConstantFolding.fold_program(program);
+        assert_eq!(folded.string(), "let f = fn() { (3 + x); };(f(7) * 11);");
+    }
+}
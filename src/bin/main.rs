@@ -1,28 +1,187 @@
-use monkey_rs::lexer::Lexer;
-use monkey_rs::token::{Token, TokenType};
-use std::io;
-use std::io::prelude::*;
+use monkey_rs::ast::serialize::Value;
+use monkey_rs::ast::{json, sexpr, Encode};
+use monkey_rs::{eval, formatter, repl, script};
+use std::io::{self, IsTerminal, Read};
+use std::{env, fs, process, thread};
+
+const USAGE: &str = "usage: monkey_rs [run] [path | -]
+       monkey_rs fmt [--check] [path...]
+       monkey_rs parse --emit=json|sexpr [path | -]";
 
 fn main() {
-    const PROMPT: &str = ">> ";
-
-    // FIXME: arbitrary Reader
-    loop {
-        print!("{}", PROMPT);
-        std::io::stdout().flush().expect("Cannot flush stdout");
-        let mut input = String::new();
-        if let Ok(_) = std::io::stdin().read_line(&mut input) {
-            let mut l = Lexer::new(input);
-            loop {
-                let tok = l.next_token();
-                if tok.t == TokenType::EOF {
-                    break;
-                }
-
-                println!("{:?}", tok);
+    // NOTE: deeply recursive scripts need more stack than the main thread has
+    let code = thread::Builder::new()
+        .stack_size(eval::STACK_SIZE)
+        .spawn(command)
+        .expect("cannot start the interpreter thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    process::exit(code);
+}
+
+fn command() -> i32 {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] if io::stdin().is_terminal() => interactive(),
+        [] | ["run"] | ["run", "-"] | ["-"] => run_stdin(),
+        ["fmt", "--check", paths @ ..] => fmt(paths, true),
+        ["fmt", paths @ ..] => fmt(paths, false),
+        ["parse", "--emit=json", path @ ..] if path.len() <= 1 => parse(path, json::write),
+        ["parse", "--emit=sexpr", path @ ..] if path.len() <= 1 => parse(path, sexpr::write),
+        ["run", path] if !path.starts_with('-') => run_file(path),
+        // NOTE: a subcommand missing its arguments isn't the name of a script
+        ["run", ..] | ["parse", ..] => usage(),
+        [path] if !path.starts_with('-') => run_file(path),
+        _ => usage(),
+    }
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+fn interactive() -> i32 {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match repl::start(stdin.lock(), stdout.lock()) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", error);
+            1
+        }
+    }
+}
+
+fn run_stdin() -> i32 {
+    let mut source = String::new();
+    match io::stdin().read_to_string(&mut source) {
+        Ok(_) => run("<stdin>", &source),
+        Err(error) => {
+            eprintln!("<stdin>: {}", error);
+            1
+        }
+    }
+}
+
+fn run_file(path: &str) -> i32 {
+    match fs::read_to_string(path) {
+        Ok(source) => run(path, &source),
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            1
+        }
+    }
+}
+
+fn run(name: &str, source: &str) -> i32 {
+    match script::run(name, source, &mut io::stderr()) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            eprintln!("{}", error);
+            1
+        }
+    }
+}
+
+/// Formats files in place, or with `check` only reports the ones that aren't
+/// formatted. Without paths, formats stdin to stdout.
+fn fmt(paths: &[&str], check: bool) -> i32 {
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            eprintln!("<stdin>: {}", error);
+            return 1;
+        }
+        return match format_source("<stdin>", &source) {
+            Some(formatted) if check => (formatted != source) as i32,
+            Some(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            None => 1,
+        };
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                code = 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(path, &source) {
+            Some(formatted) => formatted,
+            None => {
+                code = 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("{}: not formatted", path);
+            code = 1;
+        } else if let Err(error) = fs::write(path, formatted) {
+            eprintln!("{}: {}", path, error);
+            code = 1;
+        }
+    }
+    code
+}
+
+fn format_source(name: &str, source: &str) -> Option<String> {
+    match formatter::format(source) {
+        Ok(formatted) => Some(formatted),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: error: {}", name, error);
+            }
+            None
+        }
+    }
+}
+
+/// Prints the AST of a file, or of stdin without one, in the form `write`
+/// writes.
+fn parse(path: &[&str], write: fn(&Value, usize, &mut String)) -> i32 {
+    let (name, source) = match path {
+        [] | ["-"] => {
+            let mut source = String::new();
+            (
+                "<stdin>",
+                io::stdin().read_to_string(&mut source).map(|_| source),
+            )
+        }
+        [path, ..] => (*path, fs::read_to_string(path)),
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", name, error);
+            return 1;
+        }
+    };
+
+    match monkey_rs::parse(&source) {
+        Ok(program) => {
+            let mut out = String::new();
+            write(&program.encode(), 0, &mut out);
+            println!("{}", out);
+            0
+        }
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: error: {}", name, error);
             }
-        } else {
-            break;
+            1
         }
     }
 }
@@ -0,0 +1,289 @@
+use std::fmt::Write;
+
+/// Encoded bytecode: each instruction is an opcode byte followed by its
+/// operands, big-endian.
+pub type Instructions = Vec<u8>;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    True,
+    False,
+    Null,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    Call,
+    ReturnValue,
+    Return,
+    Array,
+    Hash,
+    Index,
+    GetBuiltin,
+    Closure,
+    GetFree,
+    CurrentClosure,
+    GetCell,
+    SetCell,
+}
+
+/// Every opcode, in encoding order.
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::GetBuiltin,
+    Opcode::Closure,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::GetCell,
+    Opcode::SetCell,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    pub fn definition(self) -> Definition {
+        let (name, operand_widths): (&str, &[usize]) = match self {
+            Opcode::Constant => ("OpConstant", &[2]),
+            Opcode::Pop => ("OpPop", &[]),
+            Opcode::Add => ("OpAdd", &[]),
+            Opcode::Sub => ("OpSub", &[]),
+            Opcode::Mul => ("OpMul", &[]),
+            Opcode::Div => ("OpDiv", &[]),
+            Opcode::True => ("OpTrue", &[]),
+            Opcode::False => ("OpFalse", &[]),
+            Opcode::Null => ("OpNull", &[]),
+            Opcode::Equal => ("OpEqual", &[]),
+            Opcode::NotEqual => ("OpNotEqual", &[]),
+            Opcode::GreaterThan => ("OpGreaterThan", &[]),
+            Opcode::LessThan => ("OpLessThan", &[]),
+            Opcode::Minus => ("OpMinus", &[]),
+            Opcode::Bang => ("OpBang", &[]),
+            Opcode::JumpNotTruthy => ("OpJumpNotTruthy", &[2]),
+            Opcode::Jump => ("OpJump", &[2]),
+            Opcode::GetGlobal => ("OpGetGlobal", &[2]),
+            Opcode::SetGlobal => ("OpSetGlobal", &[2]),
+            Opcode::GetLocal => ("OpGetLocal", &[1]),
+            Opcode::SetLocal => ("OpSetLocal", &[1]),
+            Opcode::Call => ("OpCall", &[1]),
+            Opcode::ReturnValue => ("OpReturnValue", &[]),
+            Opcode::Return => ("OpReturn", &[]),
+            Opcode::Array => ("OpArray", &[2]),
+            Opcode::Hash => ("OpHash", &[2]),
+            Opcode::Index => ("OpIndex", &[]),
+            Opcode::GetBuiltin => ("OpGetBuiltin", &[1]),
+            Opcode::Closure => ("OpClosure", &[2, 1]),
+            Opcode::GetFree => ("OpGetFree", &[1]),
+            Opcode::CurrentClosure => ("OpCurrentClosure", &[]),
+            Opcode::GetCell => ("OpGetCell", &[]),
+            Opcode::SetCell => ("OpSetCell", &[]),
+        };
+        Definition {
+            name,
+            operand_widths,
+        }
+    }
+}
+
+/// The name of an opcode and the width in bytes of each of its operands.
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+impl Definition {
+    /// Whether every operand is small enough for the width it's given.
+    pub fn fits(&self, operands: &[usize]) -> bool {
+        operands
+            .iter()
+            .zip(self.operand_widths)
+            .all(|(&operand, &width)| operand >> (8 * width) == 0)
+    }
+}
+
+/// Encodes one instruction. Panics if an operand doesn't fit its width; check
+/// with [`Definition::fits`] first.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let definition = op.definition();
+    assert!(
+        definition.fits(operands),
+        "operands {:?} don't fit {}",
+        operands,
+        definition.name
+    );
+    let length: usize = 1 + definition.operand_widths.iter().sum::<usize>();
+
+    let mut instruction = Vec::with_capacity(length);
+    instruction.push(op as u8);
+    for (operand, width) in operands.iter().zip(definition.operand_widths) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            1 => instruction.push(*operand as u8),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands of an instruction from `instructions`, which starts
+/// right after the opcode. Returns the operands and how many bytes they took.
+pub fn read_operands(definition: &Definition, instructions: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(definition.operand_widths.len());
+    let mut offset = 0;
+    for width in definition.operand_widths {
+        match width {
+            2 => operands.push(read_u16(&instructions[offset..]) as usize),
+            1 => operands.push(instructions[offset] as usize),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(instructions: &[u8]) -> u16 {
+    u16::from_be_bytes([instructions[0], instructions[1]])
+}
+
+/// Human-readable listing of `instructions`, one instruction per line, prefixed
+/// by its offset.
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < instructions.len() {
+        let definition = match Opcode::from_byte(instructions[i]) {
+            Some(op) => op.definition(),
+            None => {
+                writeln!(out, "{:04} ERROR: unknown opcode {}", i, instructions[i]).unwrap();
+                i += 1;
+                continue;
+            }
+        };
+
+        let (operands, read) = read_operands(&definition, &instructions[i + 1..]);
+        write!(out, "{:04} {}", i, definition.name).unwrap();
+        for operand in operands {
+            write!(out, " {}", operand).unwrap();
+        }
+        out.push('\n');
+
+        i += 1 + read;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_instructions() {
+        let tests = [
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (
+                Opcode::GetLocal,
+                vec![255],
+                vec![Opcode::GetLocal as u8, 255],
+            ),
+        ];
+
+        for (op, operands, expected) in tests {
+            assert_eq!(make(op, &operands), expected);
+        }
+    }
+
+    #[test]
+    fn read_instruction_operands() {
+        let tests = [
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Closure, vec![65535, 255], 3),
+        ];
+
+        for (op, operands, bytes_read) in tests {
+            let instruction = make(op, &operands);
+            let definition = op.definition();
+            let (read, n) = read_operands(&definition, &instruction[1..]);
+            assert_eq!(n, bytes_read);
+            assert_eq!(read, operands);
+        }
+    }
+
+    #[test]
+    fn disassemble_instructions() {
+        let instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535, 255]),
+        ]
+        .concat();
+
+        assert_eq!(
+            disassemble(&instructions),
+            "0000 OpAdd\n0001 OpGetLocal 1\n0003 OpConstant 2\n0006 OpConstant 65535\n\
+             0009 OpClosure 65535 255\n"
+        );
+    }
+
+    #[test]
+    fn opcode_round_trip() {
+        for byte in 0..=u8::MAX {
+            if let Some(op) = Opcode::from_byte(byte) {
+                assert_eq!(op as u8, byte);
+            }
+        }
+        assert_eq!(
+            Opcode::from_byte(Opcode::Return as u8),
+            Some(Opcode::Return)
+        );
+    }
+}
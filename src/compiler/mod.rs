@@ -0,0 +1,812 @@
+mod symbol_table;
+
+pub use self::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+use crate::ast::visit::{walk_expression, walk_statement, Visitor};
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::code::{make, Instructions, Opcode};
+use crate::object::{Builtins, CompiledFunction, HashKey, Object};
+use crate::token::{Span, Token};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+/// The output of the compiler: the instructions of the top-level program and
+/// the constants they refer to.
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object<'static>>,
+    /// The names of the global slots, for reporting a global that is used
+    /// before it is bound.
+    pub globals: Vec<String>,
+}
+
+/// A program the compiler cannot lower to bytecode, with the location of the
+/// expression at fault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl CompileError {
+    fn new(message: String, token: &Token) -> CompileError {
+        CompileError {
+            message,
+            span: token.span,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)
+    }
+}
+
+impl Error for CompileError {}
+
+#[derive(Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+/// The instructions of one function being compiled.
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    last_instruction: Option<EmittedInstruction>,
+    previous_instruction: Option<EmittedInstruction>,
+}
+
+/// Lowers a [`Program`] to [`Bytecode`] for the [`Vm`](crate::vm::Vm).
+///
+/// Globals and constants are kept across calls to [`Compiler::compile`], so a
+/// compiler can be fed a program piece by piece.
+pub struct Compiler {
+    constants: Vec<Object<'static>>,
+    /// Where each integer and string constant is, so equal ones are shared.
+    constant_indexes: HashMap<HashKey, usize>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    /// A compiler for programs run with the standard builtins.
+    pub fn new() -> Compiler {
+        Compiler::with_builtins(&Builtins::standard())
+    }
+
+    /// A compiler for programs run with `builtins`. The VM running the
+    /// bytecode must be given the same builtins.
+    pub fn with_builtins(builtins: &Builtins) -> Compiler {
+        let mut symbol_table = SymbolTable::new();
+        for (index, builtin) in builtins.iter().enumerate() {
+            symbol_table.define_builtin(index, &builtin.name);
+        }
+
+        Compiler {
+            constants: Vec::new(),
+            constant_indexes: HashMap::new(),
+            symbol_table,
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), CompileError> {
+        for statement in &program.statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    pub fn bytecode(&self) -> Bytecode {
+        Bytecode {
+            instructions: self.current_scope().instructions.clone(),
+            constants: self.constants.clone(),
+            globals: self.symbol_table.names(),
+        }
+    }
+
+    fn compile_block_statement(&mut self, block: &BlockStatement) -> Result<(), CompileError> {
+        for statement in &block.statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::Let { name, value, .. } => {
+                match value {
+                    // NOTE: a function bound by `let` can refer to itself by name
+                    Expression::FunctionLiteral {
+                        token,
+                        parameters,
+                        body,
+                    } => self.compile_function(token, parameters, body, Some(&name.literal))?,
+                    _ => self.compile_expression(value)?,
+                }
+                let symbol = self.define(name)?;
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index]),
+                    _ if symbol.cell => {
+                        self.emit(Opcode::GetLocal, &[symbol.index]);
+                        self.emit(Opcode::SetCell, &[])
+                    }
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index]),
+                };
+            }
+            Statement::Return { value, .. } => {
+                self.compile_expression(value)?;
+                self.emit(Opcode::ReturnValue, &[]);
+            }
+            Statement::Expression { expression, .. } => {
+                self.compile_expression(expression)?;
+                self.emit(Opcode::Pop, &[]);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        match expression {
+            Expression::Identifier { token, value } => {
+                // NOTE: a name bound nowhere yet may be bound by the time the
+                //       code runs, further down an enclosing function or at the
+                //       top level
+                let symbol = match self.symbol_table.resolve(value) {
+                    Some(symbol) => symbol,
+                    None => match self.symbol_table.resolve_declared(value) {
+                        Some(symbol) => symbol,
+                        None => self.define_global(token)?,
+                    },
+                };
+                self.load_symbol(&symbol);
+            }
+            Expression::IntegerLiteral { token, value } => {
+                let index = self.add_constant(Object::Integer(*value), token)?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::StringLiteral { token, value } => {
+                let string = Object::String(value.as_ref().into());
+                let index = self.add_constant(string, token)?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::ArrayLiteral { token, elements } => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                if elements.len() > u16::MAX as usize {
+                    let message = "too many array elements".to_string();
+                    return Err(CompileError::new(message, token));
+                }
+                self.emit(Opcode::Array, &[elements.len()]);
+            }
+            Expression::HashLiteral { token, pairs } => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                if pairs.len() * 2 > u16::MAX as usize {
+                    let message = "too many hash pairs".to_string();
+                    return Err(CompileError::new(message, token));
+                }
+                self.emit(Opcode::Hash, &[pairs.len() * 2]);
+            }
+            Expression::Index { left, index, .. } => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Opcode::Index, &[]);
+            }
+            Expression::Boolean { value: true, .. } => {
+                self.emit(Opcode::True, &[]);
+            }
+            Expression::Boolean { value: false, .. } => {
+                self.emit(Opcode::False, &[]);
+            }
+            Expression::Prefix {
+                token,
+                operator,
+                right,
+            } => {
+                self.compile_expression(right)?;
+                match operator.as_ref() {
+                    "!" => self.emit(Opcode::Bang, &[]),
+                    "-" => self.emit(Opcode::Minus, &[]),
+                    _ => {
+                        let message = format!("unknown operator: {}", operator);
+                        return Err(CompileError::new(message, token));
+                    }
+                };
+            }
+            Expression::Infix {
+                token,
+                operator,
+                left,
+                right,
+            } => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                match operator.as_ref() {
+                    "+" => self.emit(Opcode::Add, &[]),
+                    "-" => self.emit(Opcode::Sub, &[]),
+                    "*" => self.emit(Opcode::Mul, &[]),
+                    "/" => self.emit(Opcode::Div, &[]),
+                    "<" => self.emit(Opcode::LessThan, &[]),
+                    ">" => self.emit(Opcode::GreaterThan, &[]),
+                    "==" => self.emit(Opcode::Equal, &[]),
+                    "!=" => self.emit(Opcode::NotEqual, &[]),
+                    _ => {
+                        let message = format!("unknown operator: {}", operator);
+                        return Err(CompileError::new(message, token));
+                    }
+                };
+            }
+            Expression::If {
+                token,
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(condition)?;
+                // NOTE: jump targets are patched once the branches are compiled
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[9999]);
+
+                self.compile_branch(consequence)?;
+                let jump = self.emit(Opcode::Jump, &[9999]);
+
+                let after_consequence = self.current_scope().instructions.len();
+                self.change_operand(jump_not_truthy, after_consequence, token)?;
+
+                match alternative {
+                    Some(alternative) => self.compile_branch(alternative)?,
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+
+                let after_alternative = self.current_scope().instructions.len();
+                self.change_operand(jump, after_alternative, token)?;
+            }
+            Expression::FunctionLiteral {
+                token,
+                parameters,
+                body,
+            } => self.compile_function(token, parameters, body, None)?,
+            Expression::Call {
+                token,
+                function,
+                arguments,
+            } => {
+                self.compile_expression(function)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                if arguments.len() > u8::MAX as usize {
+                    let message = "too many arguments".to_string();
+                    return Err(CompileError::new(message, token));
+                }
+                self.emit(Opcode::Call, &[arguments.len()]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles a function literal to a closure over the variables it uses from
+    /// enclosing functions. `name` is what the function is bound to, if
+    /// anything.
+    fn compile_function(
+        &mut self,
+        token: &Token,
+        parameters: &[Token],
+        body: &BlockStatement,
+        name: Option<&str>,
+    ) -> Result<(), CompileError> {
+        self.enter_scope();
+        let compiled = self.compile_function_body(parameters, body, name);
+        let free_symbols = self.symbol_table.free_symbols().to_vec();
+        let num_locals = self.symbol_table.num_definitions();
+        let cells = self.symbol_table.cells();
+        let instructions = self.leave_scope();
+        compiled?;
+
+        if free_symbols.len() > u8::MAX as usize {
+            let message = "too many captured variables".to_string();
+            return Err(CompileError::new(message, token));
+        }
+        for symbol in &free_symbols {
+            self.load_slot(symbol);
+        }
+
+        let function = CompiledFunction {
+            instructions,
+            num_locals,
+            num_parameters: parameters.len(),
+            cells: cells
+                .into_iter()
+                .map(|(index, name)| (index, name.into()))
+                .collect(),
+        };
+        let index = self.add_constant(Object::CompiledFunction(Rc::new(function)), token)?;
+        self.emit(Opcode::Closure, &[index, free_symbols.len()]);
+        Ok(())
+    }
+
+    /// Compiles the body of a function in the scope entered for it.
+    fn compile_function_body(
+        &mut self,
+        parameters: &[Token],
+        body: &BlockStatement,
+        name: Option<&str>,
+    ) -> Result<(), CompileError> {
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(name);
+        }
+        let mut declarations = Declarations::default();
+        declarations.visit_block_statement(body);
+        for name in &declarations.names {
+            self.symbol_table.declare(name);
+        }
+        for parameter in parameters {
+            self.define(parameter)?;
+        }
+        self.compile_block_statement(body)?;
+
+        if self.last_instruction_is(Opcode::Pop) {
+            self.replace_last_pop_with_return();
+        }
+        if !self.last_instruction_is(Opcode::ReturnValue) {
+            self.emit(Opcode::Return, &[]);
+        }
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) {
+        self.load_slot(symbol);
+        if symbol.cell {
+            self.emit(Opcode::GetCell, &[]);
+        }
+    }
+
+    /// Pushes what the symbol's slot holds: the cell itself, for a variable
+    /// that lives in one.
+    fn load_slot(&mut self, symbol: &Symbol) {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index]),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[]),
+        };
+    }
+
+    /// Compiles one branch of an `if` so that it leaves exactly one value on the
+    /// stack: the value of its last expression statement, or null.
+    fn compile_branch(&mut self, block: &BlockStatement) -> Result<(), CompileError> {
+        self.compile_block_statement(block)?;
+        if self.last_instruction_is(Opcode::Pop) {
+            self.remove_last_pop();
+        } else {
+            self.emit(Opcode::Null, &[]);
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token) -> Result<Symbol, CompileError> {
+        let symbol = self.symbol_table.define(&name.literal);
+        check_slot(symbol, name)
+    }
+
+    fn define_global(&mut self, name: &Token) -> Result<Symbol, CompileError> {
+        let symbol = self.symbol_table.define_global(&name.literal);
+        check_slot(symbol, name)
+    }
+
+    fn add_constant(
+        &mut self,
+        object: Object<'static>,
+        token: &Token,
+    ) -> Result<usize, CompileError> {
+        let key = HashKey::of(&object).ok();
+        if let Some(&index) = key.as_ref().and_then(|key| self.constant_indexes.get(key)) {
+            return Ok(index);
+        }
+        if self.constants.len() > u16::MAX as usize {
+            return Err(CompileError::new("too many constants".to_string(), token));
+        }
+        let index = self.constants.len();
+        self.constants.push(object);
+        if let Some(key) = key {
+            self.constant_indexes.insert(key, index);
+        }
+        Ok(index)
+    }
+
+    /// Appends an instruction to the current scope and returns its position.
+    fn emit(&mut self, opcode: Opcode, operands: &[usize]) -> usize {
+        let instruction = make(opcode, operands);
+        let scope = self.current_scope_mut();
+        let position = scope.instructions.len();
+        scope.instructions.extend_from_slice(&instruction);
+
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction { opcode, position });
+        position
+    }
+
+    fn last_instruction_is(&self, opcode: Opcode) -> bool {
+        matches!(self.current_scope().last_instruction, Some(last) if last.opcode == opcode)
+    }
+
+    fn remove_last_pop(&mut self) {
+        let scope = self.current_scope_mut();
+        if let Some(last) = scope.last_instruction {
+            scope.instructions.truncate(last.position);
+            scope.last_instruction = scope.previous_instruction;
+        }
+    }
+
+    fn replace_last_pop_with_return(&mut self) {
+        let scope = self.current_scope_mut();
+        if let Some(last) = scope.last_instruction.as_mut() {
+            scope.instructions[last.position] = Opcode::ReturnValue as u8;
+            last.opcode = Opcode::ReturnValue;
+        }
+    }
+
+    /// Rewrites the operand of the jump at `position`. Fails if the target is
+    /// too far into the code for the operand to hold it.
+    fn change_operand(
+        &mut self,
+        position: usize,
+        operand: usize,
+        token: &Token,
+    ) -> Result<(), CompileError> {
+        let instructions = &mut self.current_scope_mut().instructions;
+        let opcode = Opcode::from_byte(instructions[position]).unwrap();
+        if !opcode.definition().fits(&[operand]) {
+            let message = "too much code to jump over".to_string();
+            return Err(CompileError::new(message, token));
+        }
+        let instruction = make(opcode, &[operand]);
+        instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+        Ok(())
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> Instructions {
+        let scope = self.scopes.pop().unwrap();
+        let table = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = table.into_outer().unwrap_or_default();
+        scope.instructions
+    }
+
+    fn current_scope(&self) -> &CompilationScope {
+        self.scopes.last().unwrap()
+    }
+
+    fn current_scope_mut(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+}
+
+/// Fails if the slot given to `name` can't be addressed by the instructions
+/// that use it.
+fn check_slot(symbol: Symbol, name: &Token) -> Result<Symbol, CompileError> {
+    let limit = match symbol.scope {
+        SymbolScope::Global => u16::MAX as usize,
+        _ => u8::MAX as usize,
+    };
+    if symbol.index > limit {
+        let message = format!("too many variables defining {}", name.literal);
+        return Err(CompileError::new(message, name));
+    }
+    Ok(symbol)
+}
+
+/// Collects the names a function body binds with `let`. Nested functions are
+/// left out, as they bind names of their own.
+#[derive(Default)]
+struct Declarations {
+    names: Vec<String>,
+}
+
+impl<'a> Visitor<'a> for Declarations {
+    fn visit_statement(&mut self, statement: &Statement<'a>) {
+        if let Statement::Let { name, .. } = statement {
+            self.names.push(name.literal.to_string());
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression<'a>) {
+        if !matches!(expression, Expression::FunctionLiteral { .. }) {
+            walk_expression(self, expression);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::disassemble;
+    use crate::parser::parse;
+
+    fn compile(input: &str) -> Bytecode {
+        let program = parse(input).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+        compiler.bytecode()
+    }
+
+    fn assert_instructions(actual: &[u8], expected: &[Instructions]) {
+        assert_eq!(disassemble(actual), disassemble(&expected.concat()));
+    }
+
+    fn assert_integer_constants(constants: &[Object], expected: &[i32]) {
+        let actual: Vec<String> = constants.iter().map(Object::to_string).collect();
+        let expected: Vec<String> = expected.iter().map(i32::to_string).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let bytecode = compile("1 + 2; -3");
+        assert_integer_constants(&bytecode.constants, &[1, 2, 3]);
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::Add, &[]),
+                make(Opcode::Pop, &[]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Minus, &[]),
+                make(Opcode::Pop, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let bytecode = compile("if (true) { 10 }; 3333;");
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                // 0000
+                make(Opcode::True, &[]),
+                // 0001
+                make(Opcode::JumpNotTruthy, &[10]),
+                // 0004
+                make(Opcode::Constant, &[0]),
+                // 0007
+                make(Opcode::Jump, &[11]),
+                // 0010
+                make(Opcode::Null, &[]),
+                // 0011
+                make(Opcode::Pop, &[]),
+                // 0012
+                make(Opcode::Constant, &[1]),
+                // 0015
+                make(Opcode::Pop, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn global_let_statements() {
+        let bytecode = compile("let one = 1; let two = one; two;");
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                make(Opcode::Constant, &[0]),
+                make(Opcode::SetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::SetGlobal, &[1]),
+                make(Opcode::GetGlobal, &[1]),
+                make(Opcode::Pop, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let bytecode = compile("fn(a) { let b = a + 1; b }(2)");
+        let function = match &bytecode.constants[1] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("expected a compiled function, got {:?}", other),
+        };
+        assert_eq!(function.num_parameters, 1);
+        assert_eq!(function.num_locals, 2);
+        assert_instructions(
+            &function.instructions,
+            &[
+                make(Opcode::GetLocal, &[0]),
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Add, &[]),
+                make(Opcode::SetLocal, &[1]),
+                make(Opcode::GetLocal, &[1]),
+                make(Opcode::ReturnValue, &[]),
+            ],
+        );
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                make(Opcode::Closure, &[1, 0]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Call, &[1]),
+                make(Opcode::Pop, &[]),
+            ],
+        );
+
+        let bytecode = compile("fn() { }");
+        match &bytecode.constants[0] {
+            Object::CompiledFunction(function) => {
+                assert_instructions(&function.instructions, &[make(Opcode::Return, &[])])
+            }
+            other => panic!("expected a compiled function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arrays_and_builtins() {
+        let bytecode = compile("len([1, 2])[0]");
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                make(Opcode::GetBuiltin, &[0]),
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::Array, &[2]),
+                make(Opcode::Call, &[1]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Index, &[]),
+                make(Opcode::Pop, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn hash_literals() {
+        let bytecode = compile("{1: 2 + 3}");
+        assert_instructions(
+            &bytecode.instructions,
+            &[
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Add, &[]),
+                make(Opcode::Hash, &[2]),
+                make(Opcode::Pop, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn closures() {
+        let bytecode = compile("fn(a) { fn(b) { a + b } }");
+        let instructions: Vec<String> = bytecode
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Object::CompiledFunction(function) => function.disassemble(),
+                other => panic!("expected a compiled function, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                "0000 OpGetFree 0\n0002 OpGetLocal 0\n0004 OpAdd\n0005 OpReturnValue\n",
+                "0000 OpGetLocal 0\n0002 OpClosure 0 1\n0006 OpReturnValue\n",
+            ]
+        );
+        assert_instructions(
+            &bytecode.instructions,
+            &[make(Opcode::Closure, &[1, 0]), make(Opcode::Pop, &[])],
+        );
+    }
+
+    #[test]
+    fn recursive_functions() {
+        let bytecode =
+            compile("let wrapper = fn() { let countDown = fn(x) { countDown(x - 1); }; };");
+        match &bytecode.constants[1] {
+            Object::CompiledFunction(function) => assert_eq!(
+                function.disassemble(),
+                "0000 OpCurrentClosure\n0001 OpGetLocal 0\n0003 OpConstant 0\n0006 OpSub\n\
+                 0007 OpCall 1\n0009 OpReturnValue\n"
+            ),
+            other => panic!("expected a compiled function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let arguments = vec!["0"; 256].join(", ");
+        let failing = format!("fn(a) {{ let b = 1; fn() {{ len({}) }} }}", arguments);
+        let mut compiler = Compiler::new();
+        let error = compiler.compile(&parse(&failing).unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "too many arguments at 1:30");
+
+        compiler
+            .compile(&parse("let a = 1; fn(b) { a + b }").unwrap())
+            .unwrap();
+        let bytecode = compiler.bytecode();
+        match bytecode.constants.last() {
+            Some(Object::CompiledFunction(function)) => assert_eq!(
+                function.disassemble(),
+                "0000 OpGetGlobal 0\n0003 OpGetLocal 0\n0005 OpAdd\n0006 OpReturnValue\n"
+            ),
+            other => panic!("expected a compiled function, got {:?}", other),
+        }
+        assert_eq!(bytecode.globals, ["a"]);
+    }
+
+    #[test]
+    fn shared_constants() {
+        let bytecode = compile("1 + 1; \"a\"; 2; \"a\"; 1");
+        let constants: Vec<String> = bytecode.constants.iter().map(Object::to_string).collect();
+        assert_eq!(constants, ["1", "a", "2"]);
+
+        let literals = vec!["1"; 40000].join(", ");
+        let bytecode = compile(&format!("[{0}]; [{0}]; \"a\"", literals));
+        assert_eq!(bytecode.constants.len(), 2);
+    }
+
+    #[test]
+    fn oversized_jumps() {
+        let body = "let a = 1; ".repeat(12000);
+        let tests = [
+            (format!("let b = if (true) {{ {} a }};", body), "1:9"),
+            (format!("if (true) {{ 1 }} else {{ {} a }}", body), "1:1"),
+        ];
+        for (input, location) in tests {
+            let program = parse(&input).unwrap();
+            let error = Compiler::new().compile(&program).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("too much code to jump over at {}", location)
+            );
+        }
+    }
+
+    #[test]
+    fn forward_references() {
+        let bytecode = compile("let f = fn() { let g = fn() { x }; let x = 1; g() };");
+        let instructions: Vec<String> = bytecode
+            .constants
+            .iter()
+            .filter_map(|constant| match constant {
+                Object::CompiledFunction(function) => Some(function.disassemble()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                "0000 OpGetFree 0\n0002 OpGetCell\n0003 OpReturnValue\n",
+                "0000 OpGetLocal 0\n0002 OpClosure 0 1\n0006 OpSetLocal 1\n\
+                 0008 OpConstant 1\n0011 OpGetLocal 0\n0013 OpSetCell\n\
+                 0014 OpGetLocal 1\n0016 OpCall 0\n0018 OpReturnValue\n",
+            ]
+        );
+
+        let bytecode = compile("let f = fn() { g() }; let g = 1;");
+        assert_eq!(bytecode.globals, ["g", "f"]);
+    }
+}
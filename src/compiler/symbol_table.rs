@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    /// A local of an enclosing function, captured by the closure being
+    /// compiled. `index` points into the closure's free variables.
+    Free,
+    /// One of the [`Builtins`](crate::object::Builtins), by registration order.
+    Builtin,
+    /// The function being compiled, referring to itself by the name it is
+    /// bound to.
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+    /// The variable lives in a [`Cell`](crate::object::Cell) in its slot,
+    /// because a closure captured it before it was bound.
+    pub cell: bool,
+}
+
+/// Names known to the compiler, one table per function being compiled.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+    /// Names the function binds with `let` somewhere in its body, bound yet or
+    /// not.
+    declared: HashSet<String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// A table for a function body nested in `outer`.
+    pub fn enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    /// Gives back the enclosing table, if any.
+    pub fn into_outer(self) -> Option<SymbolTable> {
+        self.outer.map(|outer| *outer)
+    }
+
+    pub fn num_definitions(&self) -> usize {
+        self.num_definitions
+    }
+
+    /// The symbols of enclosing scopes this table captured, in the order the
+    /// closure stores them.
+    pub fn free_symbols(&self) -> &[Symbol] {
+        &self.free_symbols
+    }
+
+    /// The names of this table's slots, by index.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.num_definitions];
+        for symbol in self.store.values() {
+            if let SymbolScope::Global | SymbolScope::Local = symbol.scope {
+                names[symbol.index] = symbol.name.clone();
+            }
+        }
+        names
+    }
+
+    /// The slots of this table that hold cells, with their names.
+    pub fn cells(&self) -> Vec<(usize, String)> {
+        let mut cells: Vec<(usize, String)> = self
+            .store
+            .values()
+            .filter(|symbol| symbol.scope == SymbolScope::Local && symbol.cell)
+            .map(|symbol| (symbol.index, symbol.name.clone()))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    /// Records that the function binds `name` further down, so closures
+    /// created before that can refer to it.
+    pub fn declare(&mut self, name: &str) {
+        self.declared.insert(name.to_string());
+    }
+
+    /// Binds `name` in this table. Redefining a name reuses its slot; anything
+    /// else of the same name is shadowed.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            if let SymbolScope::Global | SymbolScope::Local = symbol.scope {
+                return symbol.clone();
+            }
+        }
+
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+            cell: false,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        self.num_definitions += 1;
+        symbol
+    }
+
+    /// Binds `name` in the global table, whatever table this is. A global can
+    /// be used before the `let` that binds it runs, so a name that is bound
+    /// nowhere yet is taken to be a global bound later.
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match self.outer.as_mut() {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    /// Makes builtin number `index` visible as `name`. It doesn't take a slot.
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Builtin,
+            index,
+            cell: false,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Makes `name` refer to the function this table belongs to.
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Function,
+            index: 0,
+            cell: false,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Looks `name` up, innermost scope first. A variable of an enclosing
+    /// function is captured on the way: it becomes a free symbol of every
+    /// table between here and where it was defined.
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global | SymbolScope::Builtin => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    /// Looks up a name that no scope binds yet but that an enclosing function
+    /// declares. The variable gets its slot and cell there now, and is
+    /// captured like any other.
+    pub fn resolve_declared(&mut self, name: &str) -> Option<Symbol> {
+        let outer = self.outer.as_mut()?;
+        let symbol = if outer.outer.is_some() && outer.declared.contains(name) {
+            let symbol = outer.define(name);
+            let symbol = Symbol {
+                cell: true,
+                ..symbol
+            };
+            outer.store.insert(name.to_string(), symbol.clone());
+            symbol
+        } else {
+            outer.resolve_declared(name)?
+        };
+        Some(self.define_free(symbol))
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+            cell: original.cell,
+        };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            scope,
+            index,
+            cell: false,
+        }
+    }
+
+    #[test]
+    fn define_and_resolve() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        assert_eq!(global.define("b"), symbol("b", SymbolScope::Global, 1));
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+
+        let mut local = SymbolTable::enclosed(global);
+        assert_eq!(local.define("c"), symbol("c", SymbolScope::Local, 0));
+        assert_eq!(
+            local.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(local.resolve("c"), Some(symbol("c", SymbolScope::Local, 0)));
+        assert_eq!(local.resolve("d"), None);
+
+        let mut nested = SymbolTable::enclosed(local);
+        assert_eq!(nested.define("e"), symbol("e", SymbolScope::Local, 0));
+
+        let mut local = nested.into_outer().unwrap();
+        assert_eq!(local.num_definitions(), 1);
+        assert_eq!(local.resolve("e"), None);
+    }
+
+    #[test]
+    fn resolve_free() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        let mut first = SymbolTable::enclosed(global);
+        first.define("c");
+        let mut second = SymbolTable::enclosed(first);
+        second.define("e");
+
+        assert_eq!(
+            second.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(second.resolve("c"), Some(symbol("c", SymbolScope::Free, 0)));
+        assert_eq!(
+            second.resolve("e"),
+            Some(symbol("e", SymbolScope::Local, 0))
+        );
+        assert_eq!(second.free_symbols(), &[symbol("c", SymbolScope::Local, 0)]);
+
+        let mut third = SymbolTable::enclosed(second);
+        assert_eq!(third.resolve("c"), Some(symbol("c", SymbolScope::Free, 0)));
+        assert_eq!(third.resolve("e"), Some(symbol("e", SymbolScope::Free, 1)));
+        assert_eq!(
+            third.free_symbols(),
+            &[
+                symbol("c", SymbolScope::Free, 0),
+                symbol("e", SymbolScope::Local, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn function_name_is_shadowed() {
+        let mut table = SymbolTable::enclosed(SymbolTable::new());
+        table.define_function_name("f");
+        assert_eq!(
+            table.resolve("f"),
+            Some(symbol("f", SymbolScope::Function, 0))
+        );
+
+        assert_eq!(table.define("f"), symbol("f", SymbolScope::Local, 0));
+        assert_eq!(table.resolve("f"), Some(symbol("f", SymbolScope::Local, 0)));
+    }
+
+    #[test]
+    fn builtins() {
+        let mut global = SymbolTable::new();
+        global.define_builtin(0, "len");
+        global.define_builtin(1, "first");
+
+        let mut local = SymbolTable::enclosed(global);
+        assert_eq!(
+            local.resolve("first"),
+            Some(symbol("first", SymbolScope::Builtin, 1))
+        );
+
+        let mut global = local.into_outer().unwrap();
+        assert_eq!(global.define("len"), symbol("len", SymbolScope::Global, 0));
+        assert_eq!(
+            global.resolve("len"),
+            Some(symbol("len", SymbolScope::Global, 0))
+        );
+    }
+
+    #[test]
+    fn resolve_declared() {
+        let mut function = SymbolTable::enclosed(SymbolTable::new());
+        function.declare("later");
+        let mut nested = SymbolTable::enclosed(function);
+
+        assert_eq!(nested.resolve("later"), None);
+        let free = Symbol {
+            cell: true,
+            ..symbol("later", SymbolScope::Free, 0)
+        };
+        assert_eq!(nested.resolve_declared("later"), Some(free));
+        assert_eq!(nested.resolve_declared("global"), None);
+        assert_eq!(
+            nested.define_global("global"),
+            symbol("global", SymbolScope::Global, 0)
+        );
+
+        let mut function = nested.into_outer().unwrap();
+        let local = Symbol {
+            cell: true,
+            ..symbol("later", SymbolScope::Local, 0)
+        };
+        assert_eq!(function.define("later"), local);
+        assert_eq!(function.cells(), [(0, "later".to_string())]);
+        assert_eq!(
+            function.resolve("global"),
+            Some(symbol("global", SymbolScope::Global, 0))
+        );
+    }
+}
@@ -0,0 +1,584 @@
+//! A lossless concrete syntax tree, for tools that rewrite source and have to
+//! keep everything the AST drops: whitespace, comments, parentheses and even
+//! input that doesn't parse.
+//!
+//! The tree is a vector of tokens, each with the trivia in front of it, and
+//! nodes that cover ranges of those tokens. Printing the tokens in order gives
+//! back the source, byte for byte.
+
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser, Recorder};
+use crate::token::Token;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    /// A `{ }` block, braces included.
+    Block,
+    Identifier,
+    IntegerLiteral,
+    Boolean,
+    StringLiteral,
+    ArrayLiteral,
+    HashLiteral,
+    Prefix,
+    Infix,
+    If,
+    FunctionLiteral,
+    Call,
+    Index,
+    /// An expression in parentheses; the AST only has the expression.
+    Parenthesized,
+    /// Tokens that didn't parse as part of a statement.
+    Error,
+}
+
+impl SyntaxKind {
+    pub fn of_statement(statement: &Statement) -> SyntaxKind {
+        match statement {
+            Statement::Let { .. } => SyntaxKind::LetStatement,
+            Statement::Return { .. } => SyntaxKind::ReturnStatement,
+            Statement::Expression { .. } => SyntaxKind::ExpressionStatement,
+        }
+    }
+
+    pub fn of_expression(expression: &Expression) -> SyntaxKind {
+        match expression {
+            Expression::Identifier { .. } => SyntaxKind::Identifier,
+            Expression::IntegerLiteral { .. } => SyntaxKind::IntegerLiteral,
+            Expression::Boolean { .. } => SyntaxKind::Boolean,
+            Expression::StringLiteral { .. } => SyntaxKind::StringLiteral,
+            Expression::ArrayLiteral { .. } => SyntaxKind::ArrayLiteral,
+            Expression::HashLiteral { .. } => SyntaxKind::HashLiteral,
+            Expression::Prefix { .. } => SyntaxKind::Prefix,
+            Expression::Infix { .. } => SyntaxKind::Infix,
+            Expression::If { .. } => SyntaxKind::If,
+            Expression::FunctionLiteral { .. } => SyntaxKind::FunctionLiteral,
+            Expression::Call { .. } => SyntaxKind::Call,
+            Expression::Index { .. } => SyntaxKind::Index,
+        }
+    }
+}
+
+/// Collects the nodes the parser finishes, children before their parents.
+#[derive(Default)]
+struct Nodes(Vec<(SyntaxKind, Range<usize>)>);
+
+impl<'a> Recorder<'a> for Nodes {
+    fn statement(&mut self, statement: &Statement<'a>, tokens: Range<usize>) {
+        self.0.push((SyntaxKind::of_statement(statement), tokens));
+    }
+
+    fn expression(&mut self, expression: &Expression<'a>, tokens: Range<usize>) {
+        self.0.push((SyntaxKind::of_expression(expression), tokens));
+    }
+
+    fn parenthesized(&mut self, tokens: Range<usize>) {
+        self.0.push((SyntaxKind::Parenthesized, tokens));
+    }
+
+    fn block(&mut self, _block: &BlockStatement<'a>, tokens: Range<usize>) {
+        self.0.push((SyntaxKind::Block, tokens));
+    }
+
+    fn checkpoint(&self) -> usize {
+        self.0.len()
+    }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        self.0.truncate(checkpoint);
+    }
+}
+
+/// A token with the whitespace and comments before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken<'a> {
+    pub token: Token<'a>,
+    pub trivia: &'a str,
+    /// The token as written; a string's literal is decoded, this isn't.
+    pub text: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    /// Indexes of the tokens the node covers.
+    pub tokens: Range<usize>,
+    /// The nodes and tokens making up this node, in source order. Together
+    /// they cover `tokens` exactly.
+    pub children: Vec<SyntaxElement>,
+    /// The AST node this node stands for, as an index into [`Cst::links`].
+    ast: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    /// An index into [`Cst::tokens`].
+    Token(usize),
+}
+
+/// The concrete syntax tree of a whole source.
+#[derive(Debug, Clone)]
+pub struct Cst<'a> {
+    source: &'a str,
+    tokens: Vec<SyntaxToken<'a>>,
+    root: SyntaxNode,
+    errors: Vec<ParseError<'a>>,
+    program: Program<'a>,
+    /// For every AST node but the program, its parent's index here and its
+    /// place among the parent's children. The program is index 0.
+    links: Vec<(usize, usize)>,
+}
+
+impl<'a> Cst<'a> {
+    /// Builds the tree of any source; parts that don't parse end up in
+    /// [`SyntaxKind::Error`] nodes.
+    pub fn parse(source: &'a str) -> Cst<'a> {
+        let lexed: Vec<Token<'a>> = Lexer::new(source).collect();
+
+        let mut parser = Parser::from_tokens(lexed.iter().cloned()).with_recorder(Nodes::default());
+        let program = parser.parse_program();
+        let errors = parser.errors().to_vec();
+        let Nodes(nodes) = parser.into_recorder();
+
+        let mut trivia_start = 0;
+        let tokens: Vec<SyntaxToken<'a>> = lexed
+            .into_iter()
+            .map(|token| {
+                let span = token.span;
+                let trivia = &source[trivia_start..span.start];
+                trivia_start = span.end;
+                SyntaxToken {
+                    token,
+                    trivia,
+                    text: &source[span.start..span.end],
+                }
+            })
+            .collect();
+
+        // NOTE: nodes come children first, so a node's children are the
+        //       nodes on top of the stack that fall within it
+        let mut stack: Vec<SyntaxNode> = Vec::new();
+        for (kind, range) in nodes {
+            let mut children = Vec::new();
+            while let Some(top) = stack.last() {
+                if top.tokens.start < range.start || top.tokens.end > range.end {
+                    break;
+                }
+                children.push(stack.pop().unwrap());
+            }
+            children.reverse();
+            stack.push(SyntaxNode::new(kind, range, children));
+        }
+        let mut root = SyntaxNode::new(SyntaxKind::Program, 0..tokens.len(), stack);
+        let mut links = vec![(0, 0)];
+        root.link(0, &mut links);
+
+        Cst {
+            source,
+            tokens,
+            root,
+            errors,
+            program,
+            links,
+        }
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// Every token, ending with EOF, whose trivia is whatever follows the last
+    /// real token.
+    pub fn tokens(&self) -> &[SyntaxToken<'a>] {
+        &self.tokens
+    }
+
+    /// What the parser reported, as [`parse`](crate::parser::parse) would.
+    pub fn errors(&self) -> &[ParseError<'a>] {
+        &self.errors
+    }
+
+    /// The source of `node`, from its first token to its last, trivia in
+    /// between included.
+    pub fn text(&self, node: &SyntaxNode) -> &'a str {
+        match (
+            self.tokens.get(node.tokens.start),
+            node.tokens
+                .end
+                .checked_sub(1)
+                .and_then(|i| self.tokens.get(i)),
+        ) {
+            (Some(first), Some(last)) => &self.source[first.token.span.start..last.token.span.end],
+            _ => "",
+        }
+    }
+
+    /// The AST of the statements that parsed.
+    pub fn program(&self) -> &Program<'a> {
+        &self.program
+    }
+
+    /// The AST of a statement node.
+    pub fn statement(&self, node: &SyntaxNode) -> Option<&Statement<'a>> {
+        match self.ast(node)? {
+            AstNode::Statement(statement) => Some(statement),
+            _ => None,
+        }
+    }
+
+    /// The AST of an expression node. For a parenthesized expression, that's
+    /// the expression inside.
+    pub fn expression(&self, node: &SyntaxNode) -> Option<&Expression<'a>> {
+        match self.ast(node)? {
+            AstNode::Expression(expression) => Some(expression),
+            _ => None,
+        }
+    }
+
+    /// Follows the links of `node` up to the program, then its path back down.
+    fn ast(&self, node: &SyntaxNode) -> Option<AstNode<'_, 'a>> {
+        let mut path = Vec::new();
+        let mut id = node.ast?;
+        while id != 0 {
+            let (parent, index) = self.links[id];
+            path.push(index);
+            id = parent;
+        }
+        path.iter()
+            .rev()
+            .try_fold(AstNode::Program(&self.program), |ast, &index| {
+                ast.child(index)
+            })
+    }
+}
+
+/// A node of [`Cst::program`], for walking it by child index.
+#[derive(Clone, Copy)]
+enum AstNode<'t, 'a> {
+    Program(&'t Program<'a>),
+    Block(&'t BlockStatement<'a>),
+    Statement(&'t Statement<'a>),
+    Expression(&'t Expression<'a>),
+}
+
+impl<'t, 'a> AstNode<'t, 'a> {
+    /// The `index`th child, counting them in source order, as the nodes of
+    /// the [`SyntaxNode`] that stands for this one.
+    fn child(self, index: usize) -> Option<AstNode<'t, 'a>> {
+        use Expression::*;
+        let expression = |expression: &'t Expression<'a>| Some(AstNode::Expression(expression));
+        match self {
+            AstNode::Program(program) => program.statements.get(index).map(AstNode::Statement),
+            AstNode::Block(block) => block.statements.get(index).map(AstNode::Statement),
+            AstNode::Statement(statement) => match (statement, index) {
+                (Statement::Let { value, .. }, 0) | (Statement::Return { value, .. }, 0) => {
+                    expression(value)
+                }
+                (
+                    Statement::Expression {
+                        expression: value, ..
+                    },
+                    0,
+                ) => expression(value),
+                _ => None,
+            },
+            AstNode::Expression(node) => match (node, index) {
+                (ArrayLiteral { elements, .. }, _) => elements.get(index).map(AstNode::Expression),
+                (HashLiteral { pairs, .. }, _) => {
+                    let (key, value) = pairs.get(index / 2)?;
+                    expression([key, value][index % 2])
+                }
+                (Prefix { right, .. }, 0) => expression(right),
+                (Infix { left, .. }, 0) | (Index { left, .. }, 0) => expression(left),
+                (Infix { right, .. }, 1) => expression(right),
+                (Index { index, .. }, 1) => expression(index),
+                (If { condition, .. }, 0) => expression(condition),
+                (If { consequence, .. }, 1) => Some(AstNode::Block(consequence)),
+                (If { alternative, .. }, 2) => alternative.as_deref().map(AstNode::Block),
+                (FunctionLiteral { body, .. }, 0) => Some(AstNode::Block(body)),
+                (Call { function, .. }, 0) => expression(function),
+                (Call { arguments, .. }, _) => arguments.get(index - 1).map(AstNode::Expression),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Prints the source the tree was built from.
+impl fmt::Display for Cst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            f.write_str(token.trivia)?;
+            f.write_str(token.text)?;
+        }
+        Ok(())
+    }
+}
+
+impl SyntaxNode {
+    /// A node over `tokens`, with the tokens not covered by `nodes` filled in
+    /// around them.
+    fn new(kind: SyntaxKind, tokens: Range<usize>, nodes: Vec<SyntaxNode>) -> SyntaxNode {
+        // NOTE: in a list of statements, the only tokens that belong there
+        //       are the braces of a block and the EOF of the program
+        let statement_list = matches!(kind, SyntaxKind::Program | SyntaxKind::Block);
+        let delimiters = match kind {
+            SyntaxKind::Program => vec![tokens.end - 1],
+            SyntaxKind::Block => vec![tokens.start, tokens.end - 1],
+            _ => Vec::new(),
+        };
+
+        let mut children = Vec::new();
+        let mut error_start = None;
+        let mut nodes = nodes.into_iter().peekable();
+        let mut i = tokens.start;
+        while i < tokens.end {
+            let node = nodes.next_if(|node| node.tokens.start == i);
+            let stray = node.is_none() && statement_list && !delimiters.contains(&i);
+            match (stray, error_start) {
+                (true, None) => error_start = Some(i),
+                (false, Some(start)) => {
+                    children.push(SyntaxElement::Node(SyntaxNode::error(start..i)));
+                    error_start = None;
+                }
+                _ => {}
+            }
+
+            match node {
+                Some(node) => {
+                    i = node.tokens.end;
+                    children.push(SyntaxElement::Node(node));
+                }
+                None => {
+                    if !stray {
+                        children.push(SyntaxElement::Token(i));
+                    }
+                    i += 1;
+                }
+            }
+        }
+        if let Some(start) = error_start {
+            children.push(SyntaxElement::Node(SyntaxNode::error(start..tokens.end)));
+        }
+
+        SyntaxNode {
+            kind,
+            tokens,
+            children,
+            ast: None,
+        }
+    }
+
+    fn error(tokens: Range<usize>) -> SyntaxNode {
+        SyntaxNode {
+            kind: SyntaxKind::Error,
+            children: tokens.clone().map(SyntaxElement::Token).collect(),
+            tokens,
+            ast: None,
+        }
+    }
+
+    /// Links this node to the AST node `id`, and its children to the children
+    /// of that node. Error nodes have no AST node, and a parenthesized
+    /// expression shares its node with the expression inside.
+    fn link(&mut self, id: usize, links: &mut Vec<(usize, usize)>) {
+        self.ast = Some(id);
+        let mut index = 0;
+        for child in &mut self.children {
+            let node = match child {
+                SyntaxElement::Node(node) if node.kind != SyntaxKind::Error => node,
+                _ => continue,
+            };
+            if self.kind == SyntaxKind::Parenthesized {
+                node.link(id, links);
+            } else {
+                links.push((id, index));
+                node.link(links.len() - 1, links);
+                index += 1;
+            }
+        }
+    }
+
+    /// The child nodes, without the tokens in between.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Node;
+    use crate::parser::tests::{Rng, VOCABULARY};
+
+    /// Checks that the tree prints back as `source`, that its nodes cover
+    /// every token exactly once, in order, and that every node but the errors
+    /// leads to an AST node of its kind.
+    fn assert_lossless(source: &str) {
+        let cst = Cst::parse(source);
+        assert_eq!(cst.to_string(), source);
+
+        fn covered(node: &SyntaxNode, out: &mut Vec<usize>) {
+            for child in &node.children {
+                match child {
+                    SyntaxElement::Node(node) => covered(node, out),
+                    SyntaxElement::Token(i) => out.push(*i),
+                }
+            }
+        }
+        let mut tokens = Vec::new();
+        covered(cst.root(), &mut tokens);
+        assert_eq!(
+            tokens,
+            (0..cst.tokens().len()).collect::<Vec<_>>(),
+            "{}",
+            source
+        );
+
+        fn linked(cst: &Cst, node: &SyntaxNode) {
+            match (node.kind, cst.ast(node)) {
+                (SyntaxKind::Error, None)
+                | (SyntaxKind::Program, Some(AstNode::Program(_)))
+                | (SyntaxKind::Block, Some(AstNode::Block(_))) => {}
+                (SyntaxKind::Parenthesized, Some(AstNode::Expression(expression))) => {
+                    let inner = node.nodes().next().unwrap();
+                    assert!(std::ptr::eq(cst.expression(inner).unwrap(), expression));
+                }
+                (kind, Some(AstNode::Statement(statement))) => {
+                    assert_eq!(SyntaxKind::of_statement(statement), kind)
+                }
+                (kind, Some(AstNode::Expression(expression))) => {
+                    assert_eq!(SyntaxKind::of_expression(expression), kind)
+                }
+                (kind, _) => panic!("{:?} `{}` has no AST node", kind, cst.text(node)),
+            }
+            node.nodes().for_each(|child| linked(cst, child));
+        }
+        linked(&cst, cst.root());
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs = [
+            "",
+            "   ",
+            "#!/usr/bin/env monkey_rs\nlet x = 1;",
+            "let add = fn(a, b) {\n  // sum\n  a + /* both */ b\n};\n\nadd(1, (2));  ",
+            "{\"a\": [1, 2][0]}; if (x) { y } else { z }",
+            "let x = ; let y = 2; 3 + ; } let 5 = z; w",
+            "let s = \"unterminated",
+            "1 /* never closed",
+            "fn(x) { let = 1; x }",
+            "\u{feff}€ \"\\q\" 99999999999",
+        ];
+        for input in inputs {
+            assert_lossless(input);
+        }
+
+        let mut rng = Rng(0x5851_f42d_4c95_7f2d);
+        for _ in 0..1000 {
+            let len = rng.below(32);
+            let words: Vec<&str> = (0..len)
+                .map(|_| VOCABULARY[rng.below(VOCABULARY.len())])
+                .collect();
+            assert_lossless(&words.join(" "));
+            assert_lossless(&words.concat());
+
+            let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            assert_lossless(&String::from_utf8_lossy(&bytes));
+        }
+    }
+
+    #[test]
+    fn nodes() {
+        let source = "let x = (1 + 2) * 3; // three\nf(x)";
+        let cst = Cst::parse(source);
+
+        let statements: Vec<&SyntaxNode> = cst.root().nodes().collect();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].kind, SyntaxKind::LetStatement);
+        assert_eq!(cst.text(statements[0]), "let x = (1 + 2) * 3;");
+        assert_eq!(cst.text(statements[1]), "f(x)");
+
+        let value = statements[0].nodes().next().unwrap();
+        assert_eq!(value.kind, SyntaxKind::Infix);
+        let operands: Vec<(SyntaxKind, &str)> = value
+            .nodes()
+            .map(|node| (node.kind, cst.text(node)))
+            .collect();
+        assert_eq!(
+            operands,
+            vec![
+                (SyntaxKind::Parenthesized, "(1 + 2)"),
+                (SyntaxKind::IntegerLiteral, "3")
+            ]
+        );
+        assert_eq!(
+            cst.expression(value.nodes().next().unwrap())
+                .unwrap()
+                .string(),
+            "(1 + 2)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let source = "let x = ; let y = fn() { 1 + ; 2 };";
+        let cst = Cst::parse(source);
+        assert_eq!(cst.errors().len(), 2);
+
+        let kinds: Vec<(SyntaxKind, &str)> = cst
+            .root()
+            .nodes()
+            .map(|node| (node.kind, cst.text(node)))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (SyntaxKind::Error, "let x = ;"),
+                (SyntaxKind::LetStatement, "let y = fn() { 1 + ; 2 };"),
+            ]
+        );
+
+        let block = cst.root().nodes().nth(1).unwrap().nodes().next().unwrap();
+        let block = block.nodes().next().unwrap();
+        assert_eq!(block.kind, SyntaxKind::Block);
+        let kinds: Vec<SyntaxKind> = block.nodes().map(|node| node.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![SyntaxKind::Error, SyntaxKind::ExpressionStatement]
+        );
+    }
+
+    #[test]
+    fn derives_the_ast() {
+        let sources = [
+            crate::eval::tests::MAP_REDUCE,
+            "let x = (1 + 2) * 3; if (x > 5) { return [x, {\"a\": x}]; } else { f(x)[0] }",
+        ];
+        for source in sources {
+            let cst = Cst::parse(source);
+            let program = crate::parse(source).unwrap();
+            assert_eq!(cst.program().string(), program.string());
+
+            let derived: Vec<String> = cst
+                .root()
+                .nodes()
+                .map(|node| cst.statement(node).unwrap().string())
+                .collect();
+            let parsed: Vec<String> = program.statements.iter().map(Node::string).collect();
+            assert_eq!(derived, parsed);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::token::Span;
+use std::error::Error;
+use std::fmt;
+
+/// An error raised while running a Monkey program, by the program itself or
+/// by a builtin it called.
+///
+/// Printed, it reads like
+///
+/// ```text
+/// unknown operator: INTEGER + BOOLEAN at 3:7
+///     in add (called at 5:1)
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    /// Where the failing expression starts, when it's known.
+    pub span: Option<Span>,
+    /// The Monkey functions the error unwound through, innermost first.
+    pub trace: Vec<CallFrame>,
+}
+
+/// A call to a Monkey function that was in progress when an error was raised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    /// The name the function was called by, or `<anonymous>`.
+    pub function: String,
+    /// Where the call was made.
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new<S: Into<String>>(message: S) -> RuntimeError {
+        RuntimeError {
+            message: message.into(),
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+
+    /// The same error, located at `span`.
+    pub fn at(self, span: Span) -> RuntimeError {
+        RuntimeError {
+            span: Some(span),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}", span)?;
+        }
+        // NOTE: runaway recursion leaves a long run of the same frame; it's
+        // shown once, with a count
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            write!(f, "\n    in {} (called at {})", frame.function, frame.span)?;
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                write!(f, "\n    ... repeated {} more times", repeats)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
@@ -0,0 +1,1084 @@
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::object::{Arity, Builtins, Environment, Function, Hash, Object, MAX_CALL_DEPTH};
+use crate::token::Token;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::rc::Rc;
+
+mod error;
+
+pub use self::error::{CallFrame, RuntimeError};
+
+/// The native stack evaluation needs to recurse [`MAX_CALL_DEPTH`] calls
+/// deep; hosts run [`eval`] on a thread with a stack this size.
+///
+/// Every call recurses through a handful of Rust frames, about 5 KiB of stack
+/// in a debug build and up to 15 KiB for calls nested in literals, so the
+/// deepest recursion takes up to about 15 MiB: far more than the 2 MiB a
+/// spawned thread gets by default, or the 8 MiB of a typical main thread.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Evaluates a program with the standard builtins, leaving its top-level
+/// `let` bindings in `env`.
+///
+/// The result is the value of the last statement, or of the first `return`
+/// reached. A runtime error stops evaluation; bindings made before it stay.
+///
+/// Recursing [`MAX_CALL_DEPTH`] calls deep takes a thread with a stack of
+/// [`STACK_SIZE`].
+pub fn eval<'a>(
+    program: &Program<'a>,
+    env: &mut Environment<'a>,
+) -> Result<Object<'a>, RuntimeError> {
+    let builtins = Builtins::standard();
+    Evaluator::new(&builtins)
+        .eval_program(program, env)
+        .map_err(|error| *error)
+}
+
+/// An environment that lives across programs, plus the builtins those
+/// programs can call.
+///
+/// Hosts embedding Monkey use this to expose their own functions to scripts,
+/// or to take builtins away:
+///
+/// ```
+/// use monkey_rs::{parse, Interpreter, Object, RuntimeError};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.remove_builtin("puts");
+/// interpreter.register_builtin("double", 1, |arguments: &[Object]| match arguments[0] {
+///     Object::Integer(value) => Ok(Object::Integer(value * 2)),
+///     _ => Err(RuntimeError::new("double wants an integer")),
+/// });
+///
+/// let program = parse("double(21)").unwrap();
+/// assert_eq!(interpreter.eval(&program).unwrap().to_string(), "42");
+///
+/// let program = parse("double(true)").unwrap();
+/// let error = interpreter.eval(&program).unwrap_err();
+/// assert_eq!(error.to_string(), "double wants an integer at 1:1");
+/// ```
+pub struct Interpreter<'a> {
+    env: Environment<'a>,
+    builtins: Builtins<'a>,
+}
+
+impl<'a> Default for Interpreter<'a> {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    /// An interpreter with the standard builtins.
+    pub fn new() -> Interpreter<'a> {
+        Interpreter::with_builtins(Builtins::standard())
+    }
+
+    pub fn with_builtins(builtins: Builtins<'a>) -> Interpreter<'a> {
+        Interpreter {
+            env: Environment::new(),
+            builtins,
+        }
+    }
+
+    /// Makes `function` callable from scripts as `name`. Bindings made by the
+    /// script itself take precedence.
+    pub fn register_builtin<A, F>(&mut self, name: &str, arity: A, function: F)
+    where
+        A: Into<Arity>,
+        F: Fn(&[Object<'a>]) -> Result<Object<'a>, RuntimeError> + 'a,
+    {
+        self.builtins.register(name, arity, function);
+    }
+
+    /// Makes `puts` print to `output` rather than standard output.
+    pub fn set_output<W: Write + 'a>(&mut self, output: W) {
+        self.builtins.set_output(output);
+    }
+
+    /// Returns whether there was a builtin of that name.
+    pub fn remove_builtin(&mut self, name: &str) -> bool {
+        self.builtins.remove(name)
+    }
+
+    pub fn builtins(&self) -> &Builtins<'a> {
+        &self.builtins
+    }
+
+    pub fn env(&self) -> &Environment<'a> {
+        &self.env
+    }
+
+    /// Evaluates `program` like [`eval`], in this interpreter's environment.
+    pub fn eval(&mut self, program: &Program<'a>) -> Result<Object<'a>, RuntimeError> {
+        Evaluator::new(&self.builtins)
+            .eval_program(program, &self.env)
+            .map_err(|error| *error)
+    }
+}
+
+/// What evaluating a node gives. Errors are boxed to keep results small:
+/// they're moved through every frame of a deep recursion.
+type Evaluated<'a> = Result<Object<'a>, Box<RuntimeError>>;
+
+/// Walks the AST. Holds what every evaluation step may need besides the
+/// environment.
+struct Evaluator<'r, 'a> {
+    builtins: &'r Builtins<'a>,
+    /// How many Monkey function calls are in progress.
+    depth: Cell<usize>,
+}
+
+impl<'r, 'a> Evaluator<'r, 'a> {
+    fn new(builtins: &'r Builtins<'a>) -> Evaluator<'r, 'a> {
+        Evaluator {
+            builtins,
+            depth: Cell::new(0),
+        }
+    }
+
+    fn eval_program(&self, program: &Program<'a>, env: &Environment<'a>) -> Evaluated<'a> {
+        let mut result = Object::Null;
+
+        for statement in &program.statements {
+            result = self.eval_statement(statement, env)?;
+
+            if let Object::ReturnValue(value) = result {
+                return Ok(*value);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn eval_block_statement(
+        &self,
+        block: &BlockStatement<'a>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let mut result = Object::Null;
+
+        for statement in &block.statements {
+            result = self.eval_statement(statement, env)?;
+
+            // NOTE: keep the ReturnValue wrapped so that enclosing blocks stop too
+            if let Object::ReturnValue(_) = result {
+                return Ok(result);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn eval_statement(&self, statement: &Statement<'a>, env: &Environment<'a>) -> Evaluated<'a> {
+        match statement {
+            Statement::Let { name, value, .. } => {
+                let value = self.eval_expression(value, env)?;
+                env.set(name.literal.clone(), value);
+                Ok(Object::Null)
+            }
+            Statement::Return { value, .. } => {
+                let value = self.eval_expression(value, env)?;
+                Ok(Object::ReturnValue(Box::new(value)))
+            }
+            Statement::Expression { expression, .. } => self.eval_expression(expression, env),
+        }
+    }
+
+    fn eval_expression(&self, expression: &Expression<'a>, env: &Environment<'a>) -> Evaluated<'a> {
+        // NOTE: every Monkey call goes through here a few times, so anything
+        // bulkier than a call stays out of this frame; it's what bounds how
+        // deep recursion can go
+        match expression {
+            Expression::Identifier { value, .. } => self.eval_identifier(value, expression, env),
+            Expression::IntegerLiteral { value, .. } => Ok(Object::Integer(*value)),
+            Expression::Boolean { value, .. } => Ok(Object::Boolean(*value)),
+            Expression::StringLiteral { value, .. } => Ok(Object::String(value.as_ref().into())),
+            Expression::ArrayLiteral { elements, .. } => self.eval_array_literal(elements, env),
+            Expression::HashLiteral { pairs, .. } => self.eval_hash_literal(pairs, env),
+            Expression::Prefix {
+                operator, right, ..
+            } => self.eval_prefix(operator, right, expression, env),
+            Expression::Infix {
+                operator,
+                left,
+                right,
+                ..
+            } => self.eval_infix(operator, left, right, expression, env),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => self.eval_if(condition, consequence, alternative.as_deref(), env),
+            Expression::FunctionLiteral {
+                parameters, body, ..
+            } => Ok(function_literal(parameters, body, env)),
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => self.eval_call(function, arguments, env),
+            Expression::Index { left, index, .. } => self.eval_index(left, index, expression, env),
+        }
+    }
+
+    fn eval_identifier(
+        &self,
+        name: &str,
+        expression: &Expression<'a>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        match env.get(name) {
+            Some(object) => Ok(object),
+            None => match self.builtins.get(name) {
+                Some(builtin) => Ok(Object::Builtin(builtin.clone())),
+                None => Err(located(
+                    format!("identifier not found: {}", name),
+                    expression,
+                )),
+            },
+        }
+    }
+
+    fn eval_prefix(
+        &self,
+        operator: &str,
+        right: &Expression<'a>,
+        expression: &Expression<'a>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let right = self.eval_expression(right, env)?;
+        eval_prefix_expression(operator, right).map_err(|message| located(message, expression))
+    }
+
+    fn eval_infix(
+        &self,
+        operator: &str,
+        left: &Expression<'a>,
+        right: &Expression<'a>,
+        expression: &Expression<'a>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let left = self.eval_expression(left, env)?;
+        let right = self.eval_expression(right, env)?;
+        eval_infix_expression(operator, left, right).map_err(|message| located(message, expression))
+    }
+
+    fn eval_if(
+        &self,
+        condition: &Expression<'a>,
+        consequence: &BlockStatement<'a>,
+        alternative: Option<&BlockStatement<'a>>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let condition = self.eval_expression(condition, env)?;
+
+        if condition.is_truthy() {
+            self.eval_block_statement(consequence, env)
+        } else if let Some(alternative) = alternative {
+            self.eval_block_statement(alternative, env)
+        } else {
+            Ok(Object::Null)
+        }
+    }
+
+    fn eval_call(
+        &self,
+        function: &Expression<'a>,
+        arguments: &[Expression<'a>],
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let callee = self.eval_expression(function, env)?;
+        let arguments = self.eval_expressions(arguments, env)?;
+        match callee {
+            Object::Function(callee) => self.apply_function(&callee, arguments, function),
+            other => apply_other(other, arguments, function),
+        }
+    }
+
+    fn eval_index(
+        &self,
+        left: &Expression<'a>,
+        index: &Expression<'a>,
+        expression: &Expression<'a>,
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let left = self.eval_expression(left, env)?;
+        let index = self.eval_expression(index, env)?;
+        eval_index_expression(left, index).map_err(|message| located(message, expression))
+    }
+
+    /// Evaluates `expressions` in order, stopping at the first error.
+    fn eval_expressions(
+        &self,
+        expressions: &[Expression<'a>],
+        env: &Environment<'a>,
+    ) -> Result<Vec<Object<'a>>, Box<RuntimeError>> {
+        expressions
+            .iter()
+            .map(|expression| self.eval_expression(expression, env))
+            .collect()
+    }
+
+    fn eval_array_literal(
+        &self,
+        elements: &[Expression<'a>],
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        Ok(Object::Array(self.eval_expressions(elements, env)?.into()))
+    }
+
+    fn eval_hash_literal(
+        &self,
+        pairs: &[(Expression<'a>, Expression<'a>)],
+        env: &Environment<'a>,
+    ) -> Evaluated<'a> {
+        let mut hash = Hash::new();
+        for (key_expression, value) in pairs {
+            let key = self.eval_expression(key_expression, env)?;
+            let value = self.eval_expression(value, env)?;
+            hash.insert(key, value)
+                .map_err(|message| located(message, key_expression))?;
+        }
+        Ok(Object::Hash(Rc::new(hash)))
+    }
+
+    /// Calls `function`, which `callee` evaluated to. Errors raised by the
+    /// call itself are located at `callee`; errors from inside the body get a
+    /// frame for the call added to their trace.
+    fn apply_function(
+        &self,
+        function: &Function<'a>,
+        arguments: Vec<Object<'a>>,
+        callee: &Expression<'a>,
+    ) -> Evaluated<'a> {
+        if function.parameters.len() != arguments.len() {
+            return Err(wrong_number_of_arguments(function, &arguments, callee));
+        }
+        if self.depth.get() >= MAX_CALL_DEPTH {
+            return Err(located("stack overflow".to_string(), callee));
+        }
+
+        let env = bind_arguments(function, arguments);
+        self.depth.set(self.depth.get() + 1);
+        let result = self.eval_block_statement(&function.body, &env);
+        self.depth.set(self.depth.get() - 1);
+
+        match result {
+            Ok(Object::ReturnValue(value)) => Ok(*value),
+            Ok(result) => Ok(result),
+            Err(error) => Err(called_from(error, callee)),
+        }
+    }
+}
+
+/// The environment `function`'s body runs in, with `arguments` bound to its
+/// parameters.
+fn bind_arguments<'a>(function: &Function<'a>, arguments: Vec<Object<'a>>) -> Environment<'a> {
+    let env = Environment::enclosed(&function.env);
+    for (parameter, argument) in function.parameters.iter().zip(arguments) {
+        env.set(parameter.literal.clone(), argument);
+    }
+    env
+}
+
+/// Calls a value that isn't a Monkey function: a builtin, or something that
+/// can't be called at all.
+fn apply_other<'a>(
+    function: Object<'a>,
+    arguments: Vec<Object<'a>>,
+    callee: &Expression<'a>,
+) -> Evaluated<'a> {
+    match function {
+        Object::Builtin(builtin) => builtin
+            .call(&arguments)
+            .map_err(|error| Box::new(error.at(callee.token().span))),
+        other => Err(located(
+            format!("not a function: {}", other.type_name()),
+            callee,
+        )),
+    }
+}
+
+fn wrong_number_of_arguments(
+    function: &Function,
+    arguments: &[Object],
+    callee: &Expression,
+) -> Box<RuntimeError> {
+    located(
+        format!(
+            "wrong number of arguments: want={}, got={}",
+            function.parameters.len(),
+            arguments.len()
+        ),
+        callee,
+    )
+}
+
+/// `error`, which unwound out of the function `callee` called.
+fn called_from(mut error: Box<RuntimeError>, callee: &Expression) -> Box<RuntimeError> {
+    let function = match callee {
+        Expression::Identifier { value, .. } => value.to_string(),
+        _ => "<anonymous>".to_string(),
+    };
+    error.trace.push(CallFrame {
+        function,
+        span: callee.token().span,
+    });
+    error
+}
+
+fn function_literal<'a>(
+    parameters: &Rc<[Token<'a>]>,
+    body: &Rc<BlockStatement<'a>>,
+    env: &Environment<'a>,
+) -> Object<'a> {
+    Object::Function(Rc::new(Function {
+        parameters: parameters.clone(),
+        body: body.clone(),
+        env: env.clone(),
+    }))
+}
+
+/// An error raised by `expression` itself, located at its token.
+fn located(message: String, expression: &Expression) -> Box<RuntimeError> {
+    Box::new(RuntimeError::new(message).at(expression.token().span))
+}
+
+fn eval_prefix_expression<'a>(operator: &str, right: Object<'a>) -> Result<Object<'a>, String> {
+    match (operator, right) {
+        ("!", right) => Ok(Object::Boolean(!right.is_truthy())),
+        ("-", Object::Integer(value)) => Ok(Object::Integer(value.wrapping_neg())),
+        (_, right) => Err(format!(
+            "unknown operator: {}{}",
+            operator,
+            right.type_name()
+        )),
+    }
+}
+
+fn eval_infix_expression<'a>(
+    operator: &str,
+    left: Object<'a>,
+    right: Object<'a>,
+) -> Result<Object<'a>, String> {
+    match (&left, &right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            eval_integer_infix_expression(operator, *left, *right)
+        }
+        (Object::String(left), Object::String(right)) => {
+            eval_string_infix_expression(operator, left, right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == "==" => {
+            Ok(Object::Boolean(left == right))
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == "!=" => {
+            Ok(Object::Boolean(left != right))
+        }
+        (Object::Null, Object::Null) if operator == "==" => Ok(Object::Boolean(true)),
+        (Object::Null, Object::Null) if operator == "!=" => Ok(Object::Boolean(false)),
+        _ if left.type_name() != right.type_name() => Err(format!(
+            "type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+        _ => Err(format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+    }
+}
+
+fn eval_integer_infix_expression<'a>(
+    operator: &str,
+    left: i32,
+    right: i32,
+) -> Result<Object<'a>, String> {
+    match operator {
+        "+" => Ok(Object::Integer(left.wrapping_add(right))),
+        "-" => Ok(Object::Integer(left.wrapping_sub(right))),
+        "*" => Ok(Object::Integer(left.wrapping_mul(right))),
+        "/" if right == 0 => Err("division by zero".to_string()),
+        "/" => Ok(Object::Integer(left.wrapping_div(right))),
+        "<" => Ok(Object::Boolean(left < right)),
+        ">" => Ok(Object::Boolean(left > right)),
+        "==" => Ok(Object::Boolean(left == right)),
+        "!=" => Ok(Object::Boolean(left != right)),
+        _ => Err(format!("unknown operator: INTEGER {} INTEGER", operator)),
+    }
+}
+
+fn eval_string_infix_expression<'a>(
+    operator: &str,
+    left: &str,
+    right: &str,
+) -> Result<Object<'a>, String> {
+    match operator {
+        "+" => Ok(Object::String([left, right].concat().into())),
+        "==" => Ok(Object::Boolean(left == right)),
+        "!=" => Ok(Object::Boolean(left != right)),
+        _ => Err(format!("unknown operator: STRING {} STRING", operator)),
+    }
+}
+
+/// Out-of-range indexes give null rather than an error.
+fn eval_index_expression<'a>(left: Object<'a>, index: Object<'a>) -> Result<Object<'a>, String> {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(index)) => Ok(usize::try_from(*index)
+            .ok()
+            .and_then(|index| elements.get(index))
+            .cloned()
+            .unwrap_or(Object::Null)),
+        (Object::Hash(hash), _) => Ok(hash.get(&index)?.cloned().unwrap_or(Object::Null)),
+        _ => Err(format!(
+            "index operator not supported: {}[{}]",
+            left.type_name(),
+            index.type_name()
+        )),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    /// The value as the REPL prints it, or the error message after `ERROR: `.
+    pub(crate) fn show(result: Result<Object, RuntimeError>) -> String {
+        match result {
+            Ok(object) => object.to_string(),
+            Err(error) => format!("ERROR: {}", error.message),
+        }
+    }
+
+    /// Runs `f` on a thread with the stack evaluation needs, as hosts do.
+    pub(crate) fn on_eval_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, f)
+                .unwrap()
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    pub(crate) fn test_eval(input: &str) -> String {
+        on_eval_stack(|| {
+            let program = parse(input).unwrap();
+            let mut env = Environment::new();
+            show(eval(&program, &mut env))
+        })
+    }
+
+    #[test]
+    fn integer_expression() {
+        let tests = [
+            ("5", "5"),
+            ("10", "10"),
+            ("-5", "-5"),
+            ("-10", "-10"),
+            ("5 + 5 + 5 + 5 - 10", "10"),
+            ("2 * 2 * 2 * 2 * 2", "32"),
+            ("-50 + 100 + -50", "0"),
+            ("5 * 2 + 10", "20"),
+            ("5 + 2 * 10", "25"),
+            ("20 + 2 * -10", "0"),
+            ("50 / 2 * 2 + 10", "60"),
+            ("2 * (5 + 10)", "30"),
+            ("3 * 3 * 3 + 10", "37"),
+            ("3 * (3 * 3) + 10", "37"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("2147483647 + 1", "-2147483648"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn boolean_expression() {
+        let tests = [
+            ("true", "true"),
+            ("false", "false"),
+            ("1 < 2", "true"),
+            ("1 > 2", "false"),
+            ("1 < 1", "false"),
+            ("1 == 1", "true"),
+            ("1 != 1", "false"),
+            ("1 == 2", "false"),
+            ("true == true", "true"),
+            ("false == false", "true"),
+            ("true == false", "false"),
+            ("true != false", "true"),
+            ("(1 < 2) == true", "true"),
+            ("(1 > 2) == true", "false"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn bang_operator() {
+        let tests = [
+            ("!true", "false"),
+            ("!false", "true"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("!!false", "false"),
+            ("!!5", "true"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn if_else_expression() {
+        let tests = [
+            ("if (true) { 10 }", "10"),
+            ("if (false) { 10 }", "null"),
+            ("if (1) { 10 }", "10"),
+            ("if (1 < 2) { 10 }", "10"),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (1 > 2) { 10 } else { 20 }", "20"),
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn string_expression() {
+        let tests = [
+            (r#""Hello World!""#, "Hello World!"),
+            (r#""Hello" + " " + "World!""#, "Hello World!"),
+            (r#""a\tb" + "\u{21}""#, "a\tb!"),
+            (r#""monkey" == "monkey""#, "true"),
+            (r#""monkey" == "donkey""#, "false"),
+            (r#""monkey" != "donkey""#, "true"),
+            (r#"let s = "mon"; s + "key" == "monkey""#, "true"),
+            (r#""a" - "b""#, "ERROR: unknown operator: STRING - STRING"),
+            (r#""a" + 1"#, "ERROR: type mismatch: STRING + INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn array_literal_and_index() {
+        let tests = [
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("[]", "[]"),
+            ("[1, 2, 3][0]", "1"),
+            ("[1, 2, 3][1 + 1]", "3"),
+            ("let i = 0; [1][i]", "1"),
+            ("let a = [1, 2, 3]; a[0] + a[1] + a[2]", "6"),
+            ("let a = [1, 2, 3]; let i = a[0]; a[i]", "2"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            ("[[1, 2], [3]][0][1]", "2"),
+            (
+                "1[0]",
+                "ERROR: index operator not supported: INTEGER[INTEGER]",
+            ),
+            ("[1, true + 1]", "ERROR: type mismatch: BOOLEAN + INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn hash_literal_and_index() {
+        let tests = [
+            (
+                r#"let two = "two";
+{
+    "one": 10 - 9,
+    two: 1 + 1,
+    "thr" + "ee": 6 / 2,
+    4: 4,
+    true: 5,
+    false: 6
+}"#,
+                "{one: 1, two: 2, three: 3, 4: 4, true: 5, false: 6}",
+            ),
+            ("{}", "{}"),
+            (r#"{"foo": 5}["foo"]"#, "5"),
+            (r#"{"foo": 5}["bar"]"#, "null"),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, "5"),
+            (r#"{}["foo"]"#, "null"),
+            ("{5: 5}[5]", "5"),
+            ("{true: 5}[true]", "5"),
+            ("{1: 1, 1: 2}[1]", "2"),
+            (
+                r#"{"name": "Monkey"}[fn(x) { x }];"#,
+                "ERROR: unusable as hash key: FUNCTION",
+            ),
+            ("{[1]: 2}", "ERROR: unusable as hash key: ARRAY"),
+            (
+                "[1][{}]",
+                "ERROR: index operator not supported: ARRAY[HASH]",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn builtin_functions() {
+        let tests = [
+            (r#"len("")"#, "0"),
+            (r#"len("four")"#, "4"),
+            (r#"len("héllo")"#, "5"),
+            ("len([1, 2, 3])", "3"),
+            (
+                "len(1)",
+                "ERROR: argument to `len` not supported, got INTEGER",
+            ),
+            (
+                r#"len("one", "two")"#,
+                "ERROR: wrong number of arguments: want=1, got=2",
+            ),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            (
+                "first(1)",
+                "ERROR: argument to `first` must be ARRAY, got INTEGER",
+            ),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([1])", "[]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; let b = push(a, 2); [a, b]", "[[1], [1, 2]]"),
+            (
+                "push(1, 1)",
+                "ERROR: argument to `push` must be ARRAY, got INTEGER",
+            ),
+            ("let len = fn(x) { 42 }; len([])", "42"),
+            ("len", "builtin len"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn return_statement() {
+        let tests = [
+            ("return 10;", "10"),
+            ("return 10; 9;", "10"),
+            ("return 2 * 5; 9;", "10"),
+            ("9; return 2 * 5; 9;", "10"),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn error_handling() {
+        let tests = [
+            ("5 + true;", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "ERROR: unknown operator: -BOOLEAN"),
+            (
+                "true + false;",
+                "ERROR: unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            (
+                "5; true + false; 5",
+                "ERROR: unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            (
+                "if (10 > 1) { true + false; }",
+                "ERROR: unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            (
+                "if (10 > 1) { if (10 > 1) { return true + false; } return 1; }",
+                "ERROR: unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "ERROR: identifier not found: foobar"),
+            ("10 / (5 - 5)", "ERROR: division by zero"),
+            ("5(1)", "ERROR: not a function: INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn let_statement() {
+        let tests = [
+            ("let a = 5; a;", "5"),
+            ("let a = 5 * 5; a;", "25"),
+            ("let a = 5; let b = a; b;", "5"),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", "15"),
+            ("let a = 5;", "null"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn function_object() {
+        assert_eq!(test_eval("fn(x) { x + 2; };"), "fn(x) { (x + 2); }");
+    }
+
+    #[test]
+    fn function_application() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", "5"),
+            ("let identity = fn(x) { return x; }; identity(5);", "5"),
+            ("let double = fn(x) { x * 2; }; double(5);", "10"),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", "10"),
+            (
+                "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+                "20",
+            ),
+            ("fn(x) { x; }(5)", "5"),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn closures() {
+        let input = "
+let newAdder = fn(x) {
+  fn(y) { x + y };
+};
+
+let addTwo = newAdder(2);
+addTwo(2);";
+
+        assert_eq!(test_eval(input), "4");
+    }
+
+    #[test]
+    fn first_class_functions() {
+        let tests = [
+            (
+                "let makeAdder = fn(x) { fn(y) { x + y } }; makeAdder(2)(3)",
+                "5",
+            ),
+            ("fn(f) { f(f(1)) }(fn(x) { x * 10 })", "100"),
+            (
+                "let compose = fn(f, g) { fn(x) { g(f(x)) } };
+                 let inc = fn(x) { x + 1 };
+                 compose(inc, fn(x) { x * 2 })(5)",
+                "12",
+            ),
+            (
+                "let counter = fn(n) { if (n == 0) { 0 } else { 1 + counter(n - 1) } };
+                 counter(50)",
+                "50",
+            ),
+            (
+                "let outer = fn() {
+                   let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+                   let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+                   even(10)
+                 };
+                 outer()",
+                "true",
+            ),
+            (MAP_REDUCE, "[[2, 4, 6, 8], 10]"),
+            (
+                "let add = fn(a, b) { a + b }; add(1)",
+                "ERROR: wrong number of arguments: want=2, got=1",
+            ),
+            (
+                "fn() { 1 }(1, 2)",
+                "ERROR: wrong number of arguments: want=0, got=2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn call_depth() {
+        let counter = "let counter = fn(n) { if (n == 0) { 0 } else { 1 + counter(n - 1) } };";
+        let tests = [
+            (
+                format!("{} counter({})", counter, MAX_CALL_DEPTH - 1),
+                (MAX_CALL_DEPTH - 1).to_string(),
+            ),
+            (
+                format!("{} counter({})", counter, MAX_CALL_DEPTH),
+                "ERROR: stack overflow".to_string(),
+            ),
+            (
+                "let f = fn() { f() }; f()".to_string(),
+                "ERROR: stack overflow".to_string(),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(&input), expected, "{}", input);
+        }
+
+        let error = on_eval_stack(|| {
+            let program = parse("let f = fn() {\n  f()\n};\nf()").unwrap();
+            eval(&program, &mut Environment::new()).unwrap_err()
+        });
+        assert_eq!(error.span.map(|span| span.to_string()).unwrap(), "2:3");
+        assert_eq!(error.trace.len(), MAX_CALL_DEPTH);
+        assert_eq!(error.trace.last().unwrap().span.to_string(), "4:1");
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "stack overflow at 2:3\n    \
+                 in f (called at 2:3)\n    \
+                 ... repeated {} more times\n    \
+                 in f (called at 4:1)",
+                MAX_CALL_DEPTH - 2
+            )
+        );
+    }
+
+    pub(crate) const MAP_REDUCE: &str = r#"
+let map = fn(arr, f) {
+  let iter = fn(arr, accumulated) {
+    if (len(arr) == 0) {
+      accumulated
+    } else {
+      iter(rest(arr), push(accumulated, f(first(arr))))
+    }
+  };
+  iter(arr, [])
+};
+let reduce = fn(arr, initial, f) {
+  let iter = fn(arr, result) {
+    if (len(arr) == 0) {
+      result
+    } else {
+      iter(rest(arr), f(result, first(arr)))
+    }
+  };
+  iter(arr, initial)
+};
+let a = [1, 2, 3, 4];
+let doubled = map(a, fn(x) { x * 2 });
+let sum = reduce(a, 0, fn(acc, x) { acc + x });
+[doubled, sum]
+"#;
+
+    #[test]
+    fn environment_persists_between_programs() {
+        let mut env = Environment::new();
+
+        let program = parse("let x = 5; let double = fn(n) { n * 2 };").unwrap();
+        eval(&program, &mut env).unwrap();
+
+        let program = parse("double(x)").unwrap();
+        assert_eq!(eval(&program, &mut env).unwrap().to_string(), "10");
+    }
+
+    #[test]
+    fn error_locations() {
+        let tests = [
+            ("5 + true", "type mismatch: INTEGER + BOOLEAN at 1:3"),
+            ("-\"a\"", "unknown operator: -STRING at 1:1"),
+            (
+                "[1][\"a\"]",
+                "index operator not supported: ARRAY[STRING] at 1:4",
+            ),
+            ("{[]: 1}", "unusable as hash key: ARRAY at 1:2"),
+            (
+                "len(1)",
+                "argument to `len` not supported, got INTEGER at 1:1",
+            ),
+            (
+                "fn() { x }()",
+                "identifier not found: x at 1:8\n    in <anonymous> (called at 1:1)",
+            ),
+            (
+                "let add = fn(a, b) {\n  a + b\n};\nlet apply = fn(f) { f(1, true) };\napply(add);",
+                "type mismatch: INTEGER + BOOLEAN at 2:5\n    \
+                 in f (called at 4:21)\n    \
+                 in apply (called at 5:1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            let error = eval(&program, &mut Environment::new()).unwrap_err();
+            assert_eq!(error.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn interpreter_builtins() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_builtin("sum", Arity::Variadic, |arguments: &[Object]| {
+            let mut total = 0;
+            for argument in arguments {
+                match argument {
+                    Object::Integer(value) => total += value,
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "sum wants integers, got {}",
+                            other.type_name()
+                        )))
+                    }
+                }
+            }
+            Ok(Object::Integer(total))
+        });
+        assert!(interpreter.remove_builtin("len"));
+
+        let tests = [
+            ("sum(1, 2, 3)", "6"),
+            ("sum()", "0"),
+            ("sum(1, true)", "ERROR: sum wants integers, got BOOLEAN"),
+            ("len([])", "ERROR: identifier not found: len"),
+            ("first([1, 2])", "1"),
+            (
+                "let sum = fn(a) { a }; sum(1, 2)",
+                "ERROR: wrong number of arguments: want=1, got=2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            assert_eq!(show(interpreter.eval(&program)), expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Prints Monkey programs back as canonically laid out source, as
+//! `monkey_rs fmt` does.
+//!
+//! Every statement goes on its own line, blocks are indented by two spaces and
+//! parentheses are only kept where precedence needs them. Blank lines between
+//! statements are kept, collapsed to one. Formatting formatted source changes
+//! nothing.
+//!
+//! Comments are kept. One on a line of its own stays on a line of its own,
+//! before the statement that followed it; any other comment goes at the end of
+//! the line of the statement it was in or after.
+
+use crate::ast::{quote, BlockStatement, Expression, Statement};
+use crate::lexer::Lexer;
+use crate::parser::{parse, precedence_of_infix_operator, ParseError, Precedence};
+use crate::token::{Span, TokenType};
+use std::cell::Cell;
+use std::collections::HashMap;
+
+const INDENT: &str = "  ";
+
+/// Formats `source`, which has to parse without errors.
+pub fn format(source: &str) -> Result<String, Vec<ParseError<'_>>> {
+    let program = parse(source)?;
+
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let mut open_braces = Vec::new();
+    let mut closing_braces = HashMap::new();
+    for token in Lexer::with_comments(source) {
+        comments.extend(token.comments.iter().map(|comment| comment.span));
+        match token.t {
+            TokenType::EOF => break,
+            TokenType::LeftBrace => open_braces.push(token.span.start),
+            TokenType::RightBrace => {
+                if let Some(open) = open_braces.pop() {
+                    closing_braces.insert(open, token.span.start);
+                }
+            }
+            _ => {}
+        }
+        tokens.push(token.span);
+    }
+
+    let printer = Printer {
+        source,
+        tokens,
+        comments,
+        next_comment: Cell::new(0),
+        closing_braces,
+    };
+    let shebang = match source.lines().next() {
+        Some(line) if line.starts_with("#!") => format!("{}\n", line.trim_end()),
+        _ => String::new(),
+    };
+    Ok(shebang + &printer.statements(&program.statements, 0, source.len()))
+}
+
+struct Printer<'s> {
+    source: &'s str,
+    tokens: Vec<Span>,
+    /// Every comment in the source, in order. Those before `next_comment`
+    /// have been printed.
+    comments: Vec<Span>,
+    next_comment: Cell<usize>,
+    /// Where the `}` matching each `{` starts, by where the `{` starts.
+    closing_braces: HashMap<usize, usize>,
+}
+
+impl<'s> Printer<'s> {
+    /// One statement per line, each indented `depth` levels, followed by the
+    /// comments left before `end`.
+    fn statements(&self, statements: &[Statement], depth: usize, end: usize) -> String {
+        let mut lines = Vec::with_capacity(statements.len());
+        for (i, statement) in statements.iter().enumerate() {
+            let start = statement_start(statement);
+            let leading = self.take_comments(|comment| comment.start < start);
+            let line = self.statement(statement, depth);
+
+            // NOTE: comments within the statement that nested blocks didn't
+            //       take, and any after it on its last line, trail the line
+            let bound = statements.get(i + 1).map_or(end, statement_start);
+            let last = self.end_of_last_token_before(bound);
+            let trailing = self.take_comments(|comment| {
+                comment.start < last
+                    || (comment.start < bound && !self.source[last..comment.start].contains('\n'))
+            });
+            lines.push((leading, line, trailing));
+        }
+        let rest = self.take_comments(|comment| comment.start < end);
+
+        let mut out = String::new();
+        for (i, (statement, (leading, line, trailing))) in statements.iter().zip(&lines).enumerate()
+        {
+            self.comment_lines(&mut out, leading, depth);
+            self.separate(&mut out, statement_start(statement));
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(line);
+            if needs_semicolon(statement, lines.get(i + 1).map(|(_, next, _)| next)) {
+                out.push(';');
+            }
+            for comment in trailing {
+                out.push(' ');
+                out.push_str(self.comment_text(*comment));
+            }
+            out.push('\n');
+        }
+        self.comment_lines(&mut out, &rest, depth);
+        out
+    }
+
+    /// Prints each comment on a line of its own.
+    fn comment_lines(&self, out: &mut String, comments: &[Span], depth: usize) {
+        for comment in comments {
+            self.separate(out, comment.start);
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(self.comment_text(*comment));
+            out.push('\n');
+        }
+    }
+
+    /// Keeps an empty line the source has before `offset`, unless nothing
+    /// has been printed at this level yet.
+    fn separate(&self, out: &mut String, offset: usize) {
+        if !out.is_empty() && self.blank_line_before(offset) {
+            out.push('\n');
+        }
+    }
+
+    fn comment_text(&self, comment: Span) -> &'s str {
+        self.source[comment.start..comment.end].trim_end()
+    }
+
+    /// Takes the comments not printed yet, for as long as `wanted` holds.
+    fn take_comments(&self, wanted: impl Fn(Span) -> bool) -> Vec<Span> {
+        let start = self.next_comment.get();
+        let end = start
+            + self.comments[start..]
+                .iter()
+                .take_while(|comment| wanted(**comment))
+                .count();
+        self.next_comment.set(end);
+        self.comments[start..end].to_vec()
+    }
+
+    fn end_of_last_token_before(&self, offset: usize) -> usize {
+        let count = self.tokens.partition_point(|token| token.start < offset);
+        count.checked_sub(1).map_or(0, |last| self.tokens[last].end)
+    }
+
+    fn statement(&self, statement: &Statement, depth: usize) -> String {
+        match statement {
+            Statement::Let { name, value, .. } => {
+                format!("let {} = {}", name.literal, self.expression(value, depth))
+            }
+            Statement::Return { value, .. } => format!("return {}", self.expression(value, depth)),
+            Statement::Expression { expression, .. } => self.expression(expression, depth),
+        }
+    }
+
+    fn block(&self, block: &BlockStatement, depth: usize) -> String {
+        let end = self.closing_braces[&block.token.span.start];
+        let statements = self.statements(&block.statements, depth + 1, end);
+        if statements.is_empty() {
+            return "{}".to_string();
+        }
+        format!("{{\n{}{}}}", statements, INDENT.repeat(depth))
+    }
+
+    fn expression(&self, expression: &Expression, depth: usize) -> String {
+        match expression {
+            Expression::Identifier { value, .. } => value.to_string(),
+            Expression::IntegerLiteral { value, .. } => value.to_string(),
+            Expression::Boolean { value, .. } => value.to_string(),
+            Expression::StringLiteral { value, .. } => quote(value),
+            Expression::ArrayLiteral { elements, .. } => {
+                format!("[{}]", self.expressions(elements, depth))
+            }
+            Expression::HashLiteral { pairs, .. } => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            self.expression(key, depth),
+                            self.expression(value, depth)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Expression::Prefix {
+                operator, right, ..
+            } => {
+                let right = self.operand(right, depth, |right| right < Precedence::Prefix);
+                format!("{}{}", operator, right)
+            }
+            Expression::Infix {
+                token,
+                operator,
+                left,
+                right,
+            } => {
+                // NOTE: infix operators associate to the left, so an operand
+                //       on the right of the same precedence needs parentheses
+                let precedence = precedence_of_infix_operator(token.t);
+                format!(
+                    "{} {} {}",
+                    self.operand(left, depth, |left| left < precedence),
+                    operator,
+                    self.operand(right, depth, |right| right <= precedence)
+                )
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                let mut out = format!(
+                    "if ({}) {}",
+                    self.expression(condition, depth),
+                    self.block(consequence, depth)
+                );
+                if let Some(alternative) = alternative {
+                    out.push_str(" else ");
+                    out.push_str(&self.block(alternative, depth));
+                }
+                out
+            }
+            Expression::FunctionLiteral {
+                parameters, body, ..
+            } => {
+                let parameters: Vec<&str> = parameters.iter().map(|p| p.literal.as_ref()).collect();
+                format!("fn({}) {}", parameters.join(", "), self.block(body, depth))
+            }
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => format!(
+                "{}({})",
+                self.operand(function, depth, |function| function < Precedence::Call),
+                self.expressions(arguments, depth)
+            ),
+            Expression::Index { left, index, .. } => format!(
+                "{}[{}]",
+                self.operand(left, depth, |left| left < Precedence::Call),
+                self.expression(index, depth)
+            ),
+        }
+    }
+
+    fn expressions(&self, expressions: &[Expression], depth: usize) -> String {
+        let expressions: Vec<String> = expressions
+            .iter()
+            .map(|expression| self.expression(expression, depth))
+            .collect();
+        expressions.join(", ")
+    }
+
+    /// Formats an operand, in parentheses if `needs_parentheses` says its
+    /// precedence is too low for where it appears.
+    fn operand(
+        &self,
+        expression: &Expression,
+        depth: usize,
+        needs_parentheses: impl Fn(Precedence) -> bool,
+    ) -> String {
+        let formatted = self.expression(expression, depth);
+        if needs_parentheses(precedence(expression)) {
+            format!("({})", formatted)
+        } else {
+            formatted
+        }
+    }
+
+    /// Whether the source has an empty line right before `offset`.
+    fn blank_line_before(&self, offset: usize) -> bool {
+        let before = &self.source[..offset];
+        let gap = &before[before.trim_end().len()..];
+        gap.matches('\n').count() > 1
+    }
+}
+
+/// How tightly `expression` binds; anything that isn't an operator binds
+/// tightest.
+fn precedence(expression: &Expression) -> Precedence {
+    match expression {
+        Expression::Infix { token, .. } => precedence_of_infix_operator(token.t),
+        Expression::Prefix { .. } => Precedence::Prefix,
+        Expression::Call { .. } => Precedence::Call,
+        _ => Precedence::Index,
+    }
+}
+
+fn statement_start(statement: &Statement) -> usize {
+    match statement {
+        Statement::Let { token, .. }
+        | Statement::Return { token, .. }
+        | Statement::Expression { token, .. } => token.span.start,
+    }
+}
+
+/// Statements end in `;`, except an `if` or function literal on its own,
+/// whose `}` already ends it, unless the next statement would otherwise be
+/// read as its continuation.
+fn needs_semicolon(statement: &Statement, next: Option<&String>) -> bool {
+    match statement {
+        Statement::Expression {
+            expression: Expression::If { .. } | Expression::FunctionLiteral { .. },
+            ..
+        } => next.is_some_and(|next| next.starts_with(&['(', '[', '-'][..])),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Node;
+
+    #[test]
+    fn layout() {
+        let input = "let add=fn(a,b){a+b};let x=add(1,2)
+if(x>2){puts(\"big\")}else{puts(\"small\")}
+let empty = fn() {}; [1,2][0]; {\"a\":1, true: fn(x) { if (x) { return x; } }}";
+        let expected = r#"let add = fn(a, b) {
+  a + b;
+};
+let x = add(1, 2);
+if (x > 2) {
+  puts("big");
+} else {
+  puts("small");
+}
+let empty = fn() {};
+[1, 2][0];
+{"a": 1, true: fn(x) {
+  if (x) {
+    return x;
+  }
+}};
+"#;
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let tests = [
+            ("((a + b) + c)", "a + b + c;\n"),
+            ("a + (b + c)", "a + (b + c);\n"),
+            ("(a * b) + c", "a * b + c;\n"),
+            ("a * (b + c)", "a * (b + c);\n"),
+            ("a - (b - c) - d", "a - (b - c) - d;\n"),
+            ("(-a) * b", "-a * b;\n"),
+            ("-(a * b)", "-(a * b);\n"),
+            ("-(-a)", "--a;\n"),
+            ("!(a == b)", "!(a == b);\n"),
+            ("(a < b) == (c > d)", "a < b == c > d;\n"),
+            ("(a + b)(c)", "(a + b)(c);\n"),
+            ("(f(1))(2)[0]", "f(1)(2)[0];\n"),
+            ("(-a)[0]", "(-a)[0];\n"),
+            ("-(a[0])", "-a[0];\n"),
+            ("(fn(x) { x })(5)", "fn(x) {\n  x;\n}(5);\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(format(input).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn semicolons_after_blocks() {
+        let input = "if (a) { b }; (c); if (a) { b }; (c + 1) * 2; if (a) { b } -d";
+        assert_eq!(
+            format(input).unwrap(),
+            "if (a) {\n  b;\n}\nc;\nif (a) {\n  b;\n};\n(c + 1) * 2;\nif (a) {\n  b;\n} - d;\n"
+        );
+    }
+
+    #[test]
+    fn blank_lines() {
+        let input = "let a = 1;\n\n\n\nlet b = 2; let c = 3;\nlet f = fn() {\n  a;\n\n  b\n};";
+        assert_eq!(
+            format(input).unwrap(),
+            "let a = 1;\n\nlet b = 2;\nlet c = 3;\nlet f = fn() {\n  a;\n\n  b;\n};\n"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let input = "#!/usr/bin/env monkey_rs
+// Adds things.
+let add = fn(a, b) {
+  /* the body */ a + b // result
+
+
+  // dangling
+};
+
+let h = {\"a\": 1, // first
+  \"b\": 2};
+if (true) { /* empty */ }
+/* end */";
+        let expected = "#!/usr/bin/env monkey_rs
+// Adds things.
+let add = fn(a, b) {
+  /* the body */
+  a + b; // result
+
+  // dangling
+};
+
+let h = {\"a\": 1, \"b\": 2}; // first
+if (true) {
+  /* empty */
+}
+/* end */
+";
+        let formatted = format(input).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+
+        assert_eq!(format("// only a comment").unwrap(), "// only a comment\n");
+    }
+
+    #[test]
+    fn idempotent() {
+        let inputs = [
+            crate::eval::tests::MAP_REDUCE,
+            "if (a) { b }; (c); if (a) { b } -d;",
+            "let s = \"tab\\there \\u{1F600} \\\"quoted\\\"\"; s",
+            "fn(x) { x }(5) + -(2 * 3) - (1 - (2 - 3))",
+        ];
+
+        for input in inputs {
+            let formatted = format(input).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{}", input);
+            assert_eq!(
+                crate::parse(&formatted).unwrap().string(),
+                crate::parse(input).unwrap().string(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let errors = format("let = 1;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 use super::token::*;
+use std::borrow::Cow;
 
 trait Identifier {
     fn is_identifier(&self) -> bool;
@@ -10,182 +11,348 @@ impl Identifier for char {
     }
 }
 
-pub struct Lexer {
-    input: String,
+/// Turns Monkey source into tokens.
+///
+/// The lexer walks the input with a byte cursor: `position` is the byte offset
+/// of `current`, so every character is decoded exactly once and slicing the
+/// input is always done on character boundaries. Token literals are slices of
+/// the input, so lexing doesn't allocate.
+pub struct Lexer<'a> {
+    input: &'a str,
     position: usize,
-    read_position: usize,
     current: Option<char>,
+    line: usize,
+    column: usize,
+    finished: bool,
+    keep_comments: bool,
 }
 
-impl Lexer {
-    pub fn new(input: String) -> Self {
-        let mut l = Lexer {
+impl<'a> Lexer<'a> {
+    /// A `#!` line at the very start of `input` is skipped, so scripts can
+    /// name their interpreter.
+    pub fn new(input: &'a str) -> Self {
+        let mut lexer = Lexer {
             input,
             position: 0,
-            read_position: 0,
-            current: None,
+            current: input.chars().next(),
+            line: 1,
+            column: 1,
+            finished: false,
+            keep_comments: false,
         };
-        l.read_char();
-        l
+        if input.starts_with("#!") {
+            lexer.skip_line_comment();
+        }
+        lexer
+    }
+
+    /// A lexer that attaches comments to the token following them, for tools
+    /// that need to keep them.
+    pub fn with_comments(input: &'a str) -> Self {
+        Lexer {
+            keep_comments: true,
+            ..Lexer::new(input)
+        }
     }
 
-    pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+    pub fn next_token(&mut self) -> Token<'a> {
+        let mut comments = Vec::new();
+        loop {
+            self.skip_whitespace();
+
+            let start = self.position;
+            let line = self.line;
+            let column = self.column;
+            match (self.current, self.peek_char()) {
+                (Some('/'), Some('/')) => self.skip_line_comment(),
+                (Some('/'), Some('*')) => {
+                    if !self.skip_block_comment() {
+                        return self.token(TokenType::UnterminatedComment, start, line, column);
+                    }
+                }
+                _ => break,
+            }
 
-        let tok: Token = match self.current {
-            Some(ch @ '=') => {
+            if self.keep_comments {
+                comments.push(Comment {
+                    text: Cow::Borrowed(&self.input[start..self.position]),
+                    span: Span {
+                        start,
+                        end: self.position,
+                        line,
+                        column,
+                    },
+                });
+            }
+        }
+
+        let mut token = self.read_token();
+        token.comments = comments;
+        token
+    }
+
+    fn read_token(&mut self) -> Token<'a> {
+        let start = self.position;
+        let line = self.line;
+        let column = self.column;
+
+        let t = match self.current {
+            Some('=') => {
                 if self.peek_char() == Some('=') {
                     self.read_char();
-                    Token {
-                        t: TokenType::Eq,
-                        literal: "==".to_string(),
-                    }
+                    TokenType::Eq
                 } else {
-                    Token {
-                        t: TokenType::Assign,
-                        literal: ch.to_string(),
-                    }
+                    TokenType::Assign
                 }
             }
-            Some(ch @ '+') => Token {
-                t: TokenType::Plus,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '-') => Token {
-                t: TokenType::Minus,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '!') => {
+            Some('+') => TokenType::Plus,
+            Some('-') => TokenType::Minus,
+            Some('!') => {
                 if self.peek_char() == Some('=') {
                     self.read_char();
-                    Token {
-                        t: TokenType::NotEq,
-                        literal: "!=".to_string(),
-                    }
+                    TokenType::NotEq
                 } else {
-                    Token {
-                        t: TokenType::Bang,
-                        literal: ch.to_string(),
-                    }
+                    TokenType::Bang
                 }
             }
-            Some(ch @ '/') => Token {
-                t: TokenType::Slash,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '*') => Token {
-                t: TokenType::Asterisk,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '<') => Token {
-                t: TokenType::LT,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '>') => Token {
-                t: TokenType::GT,
-                literal: ch.to_string(),
-            },
-            Some(ch @ ';') => Token {
-                t: TokenType::Semicolon,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '(') => Token {
-                t: TokenType::LeftParen,
-                literal: ch.to_string(),
-            },
-            Some(ch @ ')') => Token {
-                t: TokenType::RightParen,
-                literal: ch.to_string(),
-            },
-            Some(ch @ ',') => Token {
-                t: TokenType::Comma,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '{') => Token {
-                t: TokenType::LeftBrace,
-                literal: ch.to_string(),
-            },
-            Some(ch @ '}') => Token {
-                t: TokenType::RightBrace,
-                literal: ch.to_string(),
-            },
-            None => Token {
-                t: TokenType::EOF,
-                literal: "".to_string(),
-            },
+            Some('/') => TokenType::Slash,
+            Some('*') => TokenType::Asterisk,
+            Some('<') => TokenType::LT,
+            Some('>') => TokenType::GT,
+            Some(';') => TokenType::Semicolon,
+            Some(':') => TokenType::Colon,
+            Some('(') => TokenType::LeftParen,
+            Some(')') => TokenType::RightParen,
+            Some(',') => TokenType::Comma,
+            Some('{') => TokenType::LeftBrace,
+            Some('}') => TokenType::RightBrace,
+            Some('[') => TokenType::LeftBracket,
+            Some(']') => TokenType::RightBracket,
+            Some('"') => return self.read_string(start, line, column),
+            None => TokenType::EOF,
             Some(ch) => {
                 if ch.is_identifier() {
                     let literal = self.read_identifier();
-                    return Token {
-                        t: lookup_ident(&literal),
-                        literal,
-                    };
-                } else if ch.is_digit(10) {
-                    let literal = self.read_number();
-                    return Token {
-                        t: TokenType::Int,
-                        literal,
-                    };
+                    return self.token(lookup_ident(literal), start, line, column);
+                } else if ch.is_ascii_digit() {
+                    self.read_number();
+                    return self.token(TokenType::Int, start, line, column);
                 } else {
-                    Token {
-                        t: TokenType::Illegal,
-                        literal: ch.to_string(),
-                    }
+                    TokenType::Illegal
                 }
             }
         };
         self.read_char();
-        tok
+        self.token(t, start, line, column)
+    }
+
+    /// Builds a token that started at `start` and ends where the lexer is now.
+    fn token(&self, t: TokenType, start: usize, line: usize, column: usize) -> Token<'a> {
+        Token {
+            t,
+            literal: Cow::Borrowed(&self.input[start..self.position]),
+            span: Span {
+                start,
+                end: self.position,
+                line,
+                column,
+            },
+            comments: Vec::new(),
+        }
     }
 
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.current = None;
-        } else {
-            // FIXME
-            self.current = self.input.chars().nth(self.read_position);
+        match self.current {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => return,
         }
-        self.position = self.read_position;
-        self.read_position += 1;
+        self.position += self.current.map_or(0, char::len_utf8);
+        self.current = self.input[self.position..].chars().next();
     }
 
-    fn read_identifier(&mut self) -> String {
-        let position = self.position;
+    fn read_identifier(&mut self) -> &'a str {
+        let start = self.position;
         while self.current.filter(char::is_identifier).is_some() {
             self.read_char();
         }
-        self.input[position..self.position].to_string()
+        &self.input[start..self.position]
+    }
+
+    /// Reads a string literal, decoding its escape sequences. The literal stays
+    /// borrowed from the input unless there's an escape to decode.
+    fn read_string(&mut self, start: usize, line: usize, column: usize) -> Token<'a> {
+        self.read_char();
+        let contents_start = self.position;
+        let mut decoded: Option<String> = None;
+        let mut invalid_escape = false;
+
+        loop {
+            match self.current {
+                None => return self.token(TokenType::UnterminatedString, start, line, column),
+                Some('"') => break,
+                Some('\\') => {
+                    let decoded = decoded.get_or_insert_with(|| {
+                        self.input[contents_start..self.position].to_string()
+                    });
+                    self.read_char();
+                    match self.read_escape() {
+                        Some(ch) => decoded.push(ch),
+                        None => invalid_escape = true,
+                    }
+                }
+                Some(ch) => {
+                    if let Some(decoded) = decoded.as_mut() {
+                        decoded.push(ch);
+                    }
+                    self.read_char();
+                }
+            }
+        }
+
+        let contents_end = self.position;
+        self.read_char();
+        if invalid_escape {
+            return self.token(TokenType::InvalidEscape, start, line, column);
+        }
+
+        let mut token = self.token(TokenType::String, start, line, column);
+        token.literal = match decoded {
+            Some(decoded) => Cow::Owned(decoded),
+            None => Cow::Borrowed(&self.input[contents_start..contents_end]),
+        };
+        token
     }
 
-    fn read_number(&mut self) -> String {
-        let position = self.position;
-        while self.current.filter(|&c| char::is_digit(c, 10)).is_some() {
+    /// Reads the escape sequence after a backslash. Stops short of a closing
+    /// quote or the end of input, so the string can still be terminated.
+    fn read_escape(&mut self) -> Option<char> {
+        let escaped = match self.current? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            'u' => {
+                self.read_char();
+                return self.read_unicode_escape();
+            }
+            _ => return None,
+        };
+        self.read_char();
+        Some(escaped)
+    }
+
+    /// Reads the `{XXXX}` part of a `\u{XXXX}` escape: one to six hex digits
+    /// naming a Unicode scalar value.
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.current != Some('{') {
+            return None;
+        }
+        self.read_char();
+
+        let digits_start = self.position;
+        while self.current.filter(char::is_ascii_hexdigit).is_some() {
+            self.read_char();
+        }
+        let digits = &self.input[digits_start..self.position];
+        if self.current != Some('}') {
+            return None;
+        }
+        self.read_char();
+
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+    }
+
+    fn read_number(&mut self) {
+        while self.current.filter(char::is_ascii_digit).is_some() {
             self.read_char()
         }
-        self.input[position..self.position].to_string()
     }
 
-    fn peek_char(&mut self) -> Option<char> {
-        self.input.chars().nth(self.read_position)
+    fn peek_char(&self) -> Option<char> {
+        let next = self.position + self.current.map_or(0, char::len_utf8);
+        self.input[next..].chars().next()
+    }
+
+    /// Skips a `//` comment, up to but not including the newline.
+    fn skip_line_comment(&mut self) {
+        while !matches!(self.current, None | Some('\n')) {
+            self.read_char();
+        }
+    }
+
+    /// Skips a `/* */` comment, nested ones included. Returns `false` if the
+    /// input ends first.
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            match (self.current, self.peek_char()) {
+                (None, _) => return false,
+                (Some('/'), Some('*')) => {
+                    self.read_char();
+                    self.read_char();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.read_char();
+                    self.read_char();
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                _ => self.read_char(),
+            }
+        }
     }
 
     fn skip_whitespace(&mut self) {
-        while match self.current {
-            Some(ch) if ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' => true,
-            _ => false,
-        } {
+        while matches!(
+            self.current,
+            Some(' ') | Some('\t') | Some('\n') | Some('\r')
+        ) {
             self.read_char();
         }
     }
 }
 
+/// Yields every token up to and including `EOF`, then stops. Use
+/// [`Lexer::next_token`] directly to keep receiving `EOF` past the end.
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = token.t == TokenType::EOF;
+        Some(token)
+    }
+}
+
+/// Lexes the whole input. The last token is always `EOF`.
+pub fn tokenize(input: &str) -> Vec<Token<'_>> {
+    Lexer::new(input).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn it_works() {
-        let input = r"let five = 5;
+        let input = r#"let five = 5;
 let ten = 10;
 
 let add = fn(x, y) {
@@ -193,7 +360,7 @@ let add = fn(x, y) {
 };
 
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
 
 if (5 < 10) {
@@ -203,312 +370,326 @@ if (5 < 10) {
 }
 
 10 == 10;
-10 != 9;"
-            .to_string();
+10 != 9;
+[1, 2];
+{"foo": "bar"}"#;
         let mut lexer = Lexer::new(input);
 
         let tests = vec![
-            Token {
-                t: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "five".to_string(),
-            },
-            Token {
-                t: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "ten".to_string(),
-            },
-            Token {
-                t: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "add".to_string(),
-            },
-            Token {
-                t: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t: TokenType::Function,
-                literal: "fn".to_string(),
-            },
-            Token {
-                t: TokenType::LeftParen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "x".to_string(),
-            },
-            Token {
-                t: TokenType::Comma,
-                literal: ",".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "y".to_string(),
-            },
-            Token {
-                t: TokenType::RightParen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t: TokenType::LeftBrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "x".to_string(),
-            },
-            Token {
-                t: TokenType::Plus,
-                literal: "+".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "y".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::RightBrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Let,
-                literal: "let".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "result".to_string(),
-            },
-            Token {
-                t: TokenType::Assign,
-                literal: "=".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "add".to_string(),
-            },
-            Token {
-                t: TokenType::LeftParen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "five".to_string(),
-            },
-            Token {
-                t: TokenType::Comma,
-                literal: ",".to_string(),
-            },
-            Token {
-                t: TokenType::Ident,
-                literal: "ten".to_string(),
-            },
-            Token {
-                t: TokenType::RightParen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Bang,
-                literal: "!".to_string(),
-            },
-            Token {
-                t: TokenType::Minus,
-                literal: "-".to_string(),
-            },
-            Token {
-                t: TokenType::Slash,
-                literal: "/".to_string(),
-            },
-            Token {
-                t: TokenType::Asterisk,
-                literal: "*".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t: TokenType::LT,
-                literal: "<".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::GT,
-                literal: ">".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::If,
-                literal: "if".to_string(),
-            },
-            Token {
-                t: TokenType::LeftParen,
-                literal: "(".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "5".to_string(),
-            },
-            Token {
-                t: TokenType::LT,
-                literal: "<".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::RightParen,
-                literal: ")".to_string(),
-            },
-            Token {
-                t: TokenType::LeftBrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t: TokenType::Return,
-                literal: "return".to_string(),
-            },
-            Token {
-                t: TokenType::True,
-                literal: "true".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::RightBrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t: TokenType::Else,
-                literal: "else".to_string(),
-            },
-            Token {
-                t: TokenType::LeftBrace,
-                literal: "{".to_string(),
-            },
-            Token {
-                t: TokenType::Return,
-                literal: "return".to_string(),
-            },
-            Token {
-                t: TokenType::False,
-                literal: "false".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::RightBrace,
-                literal: "}".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::Eq,
-                literal: "==".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "10".to_string(),
-            },
-            Token {
-                t: TokenType::NotEq,
-                literal: "!=".to_string(),
-            },
-            Token {
-                t: TokenType::Int,
-                literal: "9".to_string(),
-            },
-            Token {
-                t: TokenType::Semicolon,
-                literal: ";".to_string(),
-            },
-            Token {
-                t: TokenType::EOF,
-                literal: "".to_string(),
-            },
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "five"),
+            (TokenType::Assign, "="),
+            (TokenType::Int, "5"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "ten"),
+            (TokenType::Assign, "="),
+            (TokenType::Int, "10"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "add"),
+            (TokenType::Assign, "="),
+            (TokenType::Function, "fn"),
+            (TokenType::LeftParen, "("),
+            (TokenType::Ident, "x"),
+            (TokenType::Comma, ","),
+            (TokenType::Ident, "y"),
+            (TokenType::RightParen, ")"),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::Ident, "x"),
+            (TokenType::Plus, "+"),
+            (TokenType::Ident, "y"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::RightBrace, "}"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "result"),
+            (TokenType::Assign, "="),
+            (TokenType::Ident, "add"),
+            (TokenType::LeftParen, "("),
+            (TokenType::Ident, "five"),
+            (TokenType::Comma, ","),
+            (TokenType::Ident, "ten"),
+            (TokenType::RightParen, ")"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Bang, "!"),
+            (TokenType::Minus, "-"),
+            (TokenType::Slash, "/"),
+            (TokenType::Asterisk, "*"),
+            (TokenType::Int, "5"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Int, "5"),
+            (TokenType::LT, "<"),
+            (TokenType::Int, "10"),
+            (TokenType::GT, ">"),
+            (TokenType::Int, "5"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::If, "if"),
+            (TokenType::LeftParen, "("),
+            (TokenType::Int, "5"),
+            (TokenType::LT, "<"),
+            (TokenType::Int, "10"),
+            (TokenType::RightParen, ")"),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::Return, "return"),
+            (TokenType::True, "true"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::RightBrace, "}"),
+            (TokenType::Else, "else"),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::Return, "return"),
+            (TokenType::False, "false"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::RightBrace, "}"),
+            (TokenType::Int, "10"),
+            (TokenType::Eq, "=="),
+            (TokenType::Int, "10"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Int, "10"),
+            (TokenType::NotEq, "!="),
+            (TokenType::Int, "9"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::LeftBracket, "["),
+            (TokenType::Int, "1"),
+            (TokenType::Comma, ","),
+            (TokenType::Int, "2"),
+            (TokenType::RightBracket, "]"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::String, "foo"),
+            (TokenType::Colon, ":"),
+            (TokenType::String, "bar"),
+            (TokenType::RightBrace, "}"),
+            (TokenType::EOF, ""),
         ];
 
-        for test_token in tests {
+        for (expected_type, expected_literal) in tests {
             let current_token = lexer.next_token();
-            assert_eq!(current_token, test_token);
+            assert_eq!(current_token.t, expected_type);
+            assert_eq!(current_token.literal, expected_literal);
         }
     }
+
+    #[test]
+    fn spans() {
+        let input = "let x = 5;\n  x == 10";
+        let mut lexer = Lexer::new(input);
+
+        let tests = vec![
+            ("let", 0, 3, 1, 1),
+            ("x", 4, 5, 1, 5),
+            ("=", 6, 7, 1, 7),
+            ("5", 8, 9, 1, 9),
+            (";", 9, 10, 1, 10),
+            ("x", 13, 14, 2, 3),
+            ("==", 15, 17, 2, 5),
+            ("10", 18, 20, 2, 8),
+            ("", 20, 20, 2, 10),
+        ];
+
+        for (expected_literal, start, end, line, column) in tests {
+            let current_token = lexer.next_token();
+            assert_eq!(current_token.literal, expected_literal);
+            assert_eq!(&input[start..end], expected_literal);
+            assert_eq!(
+                current_token.span,
+                Span {
+                    start,
+                    end,
+                    line,
+                    column
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn shebang() {
+        let tokens = tokenize("#!/usr/bin/env monkey_rs\nlet x = 1;");
+        assert_eq!(tokens[0].t, TokenType::Let);
+        assert_eq!((tokens[0].span.line, tokens[0].span.column), (2, 1));
+
+        let tokens = tokenize("#!");
+        assert_eq!(tokens[0].t, TokenType::EOF);
+
+        // only on the first line
+        let tokens = tokenize(" #!");
+        assert_eq!(tokens[0].t, TokenType::Illegal);
+    }
+
+    #[test]
+    fn unicode() {
+        let input = "let 변수 = €;\nnaïve + 1";
+        let mut lexer = Lexer::new(input);
+
+        let tests = vec![
+            (TokenType::Let, "let", 1, 1),
+            (TokenType::Ident, "변수", 1, 5),
+            (TokenType::Assign, "=", 1, 8),
+            (TokenType::Illegal, "€", 1, 10),
+            (TokenType::Semicolon, ";", 1, 11),
+            (TokenType::Ident, "naïve", 2, 1),
+            (TokenType::Plus, "+", 2, 7),
+            (TokenType::Int, "1", 2, 9),
+            (TokenType::EOF, "", 2, 10),
+        ];
+
+        for (expected_type, expected_literal, line, column) in tests {
+            let current_token = lexer.next_token();
+            assert_eq!(current_token.t, expected_type);
+            assert_eq!(current_token.literal, expected_literal);
+            assert_eq!(
+                &input[current_token.span.start..current_token.span.end],
+                expected_literal
+            );
+            assert_eq!(
+                (current_token.span.line, current_token.span.column),
+                (line, column)
+            );
+        }
+    }
+
+    #[test]
+    fn literals_borrow_from_source() {
+        let input = "let add = fn(x, y) { x + y; };";
+        let mut lexer = Lexer::new(input);
+
+        loop {
+            let token = lexer.next_token();
+            match token.literal {
+                Cow::Borrowed(literal) => {
+                    assert!(std::ptr::eq(
+                        literal,
+                        &input[token.span.start..token.span.end]
+                    ))
+                }
+                Cow::Owned(_) => panic!("expected a borrowed literal for {:?}", token),
+            }
+            if token.t == TokenType::EOF {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn iterator() {
+        let types: Vec<TokenType> = Lexer::new("let x = 5;").map(|token| token.t).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Let,
+                TokenType::Ident,
+                TokenType::Assign,
+                TokenType::Int,
+                TokenType::Semicolon,
+                TokenType::EOF,
+            ]
+        );
+
+        let mut lexer = Lexer::new("");
+        assert_eq!(lexer.next().map(|token| token.t), Some(TokenType::EOF));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.next(), None);
+
+        let identifiers: Vec<String> = tokenize("add(five, ten)")
+            .into_iter()
+            .filter(|token| token.t == TokenType::Ident)
+            .map(|token| token.literal.into_owned())
+            .collect();
+        assert_eq!(identifiers, vec!["add", "five", "ten"]);
+    }
+
+    #[test]
+    fn comments() {
+        let input = "// leading\nlet x = 1; // trailing\n/* a /* nested */ block */ x / 2 /**/";
+
+        let types: Vec<TokenType> = tokenize(input).iter().map(|token| token.t).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Let,
+                TokenType::Ident,
+                TokenType::Assign,
+                TokenType::Int,
+                TokenType::Semicolon,
+                TokenType::Ident,
+                TokenType::Slash,
+                TokenType::Int,
+                TokenType::EOF,
+            ]
+        );
+
+        let comments: Vec<(TokenType, String)> = Lexer::with_comments(input)
+            .filter(|token| !token.comments.is_empty())
+            .map(|token| {
+                let texts: Vec<&str> = token.comments.iter().map(|c| c.text.as_ref()).collect();
+                (token.t, texts.join(" | "))
+            })
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                (TokenType::Let, "// leading".to_string()),
+                (
+                    TokenType::Ident,
+                    "// trailing | /* a /* nested */ block */".to_string()
+                ),
+                (TokenType::EOF, "/**/".to_string()),
+            ]
+        );
+
+        let tokens = tokenize("1 /* never /* closed */");
+        assert_eq!(tokens[1].t, TokenType::UnterminatedComment);
+        assert_eq!(tokens[1].literal, "/* never /* closed */");
+        assert_eq!(tokens[2].t, TokenType::EOF);
+    }
+
+    #[test]
+    fn strings() {
+        let tests = vec![
+            (r#""foobar""#, TokenType::String, "foobar"),
+            (r#""foo bar""#, TokenType::String, "foo bar"),
+            (r#""""#, TokenType::String, ""),
+            (r#""a\nb\t\"c\"\\""#, TokenType::String, "a\nb\t\"c\"\\"),
+            (r#""\u{48}\u{e9}\u{1F600}""#, TokenType::String, "Hé😀"),
+            (r#""\q""#, TokenType::InvalidEscape, r#""\q""#),
+            (
+                r#""\u{110000}""#,
+                TokenType::InvalidEscape,
+                r#""\u{110000}""#,
+            ),
+            (r#""\u{}""#, TokenType::InvalidEscape, r#""\u{}""#),
+            (r#""\u{41""#, TokenType::InvalidEscape, r#""\u{41""#),
+            (r#""abc"#, TokenType::UnterminatedString, r#""abc"#),
+            (r#""abc\""#, TokenType::UnterminatedString, r#""abc\""#),
+        ];
+
+        for (input, expected_type, expected_literal) in tests {
+            let mut lexer = Lexer::new(input);
+            let token = lexer.next_token();
+            assert_eq!(token.t, expected_type, "{}", input);
+            assert_eq!(token.literal, expected_literal, "{}", input);
+            assert_eq!(token.span.end, input.len(), "{}", input);
+            assert_eq!(lexer.next_token().t, TokenType::EOF, "{}", input);
+        }
+    }
+
+    #[test]
+    fn strings_borrow_unless_escaped() {
+        let tokens = tokenize(r#""plain" "esc\n""#);
+        assert!(matches!(tokens[0].literal, Cow::Borrowed("plain")));
+        assert!(matches!(tokens[1].literal, Cow::Owned(_)));
+
+        let token = Lexer::new("let s = \"one\ntwo\" + x").nth(3).unwrap();
+        assert_eq!(token.literal, "one\ntwo");
+        assert_eq!(
+            token.span,
+            Span {
+                start: 8,
+                end: 17,
+                line: 1,
+                column: 9
+            }
+        );
+    }
 }
@@ -1,4 +1,17 @@
 pub mod ast;
+pub mod code;
+pub mod compiler;
+pub mod cst;
+pub mod eval;
+pub mod formatter;
 pub mod lexer;
+pub mod object;
 pub mod parser;
+pub mod repl;
+pub mod script;
 pub mod token;
+pub mod vm;
+
+pub use eval::{eval, CallFrame, Interpreter, RuntimeError};
+pub use object::{Environment, Object};
+pub use parser::{parse, ParseError, Parser};
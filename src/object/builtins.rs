@@ -0,0 +1,243 @@
+use super::Object;
+use crate::eval::RuntimeError;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// How many arguments a builtin takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    /// Any number of arguments, checked by the function itself if at all.
+    Variadic,
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Arity {
+        Arity::Exact(count)
+    }
+}
+
+type BuiltinFunction<'a> = dyn Fn(&[Object<'a>]) -> Result<Object<'a>, RuntimeError> + 'a;
+
+/// Where `puts` prints, shared by the copies of a registry.
+type Output<'a> = Rc<RefCell<Box<dyn Write + 'a>>>;
+
+/// A function provided by the host rather than written in Monkey.
+pub struct Builtin<'a> {
+    pub name: String,
+    pub arity: Arity,
+    function: Box<BuiltinFunction<'a>>,
+}
+
+impl<'a> Builtin<'a> {
+    /// Calls the function after checking the number of arguments.
+    pub fn call(&self, arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+        if let Arity::Exact(want) = self.arity {
+            if arguments.len() != want {
+                return Err(RuntimeError::new(format!(
+                    "wrong number of arguments: want={}, got={}",
+                    want,
+                    arguments.len()
+                )));
+            }
+        }
+        (self.function)(arguments)
+    }
+}
+
+impl fmt::Debug for Builtin<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+/// The builtins a program can see, looked up by name when an identifier isn't
+/// bound in the environment.
+///
+/// Builtins are numbered in registration order; the compiler and the VM refer
+/// to them by that number, so both have to be given the same registry.
+#[derive(Clone)]
+pub struct Builtins<'a> {
+    entries: Vec<Rc<Builtin<'a>>>,
+    output: Output<'a>,
+}
+
+impl<'a> Default for Builtins<'a> {
+    fn default() -> Self {
+        Builtins::new()
+    }
+}
+
+impl<'a> Builtins<'a> {
+    /// A registry with no builtins at all.
+    pub fn new() -> Builtins<'a> {
+        Builtins {
+            entries: Vec::new(),
+            output: Rc::new(RefCell::new(Box::new(io::stdout()))),
+        }
+    }
+
+    /// `len`, `puts`, `first`, `last`, `rest` and `push`.
+    pub fn standard() -> Builtins<'a> {
+        let mut builtins = Builtins::new();
+        builtins.register("len", 1, len);
+        let output = builtins.output.clone();
+        builtins.register(
+            "puts",
+            Arity::Variadic,
+            move |arguments: &[Object<'a>]| puts(&mut *output.borrow_mut(), arguments),
+        );
+        builtins.register("first", 1, first);
+        builtins.register("last", 1, last);
+        builtins.register("rest", 1, rest);
+        builtins.register("push", 2, push);
+        builtins
+    }
+
+    /// Adds a builtin, replacing any builtin of the same name in place.
+    pub fn register<A, F>(&mut self, name: &str, arity: A, function: F)
+    where
+        A: Into<Arity>,
+        F: Fn(&[Object<'a>]) -> Result<Object<'a>, RuntimeError> + 'a,
+    {
+        let builtin = Rc::new(Builtin {
+            name: name.to_string(),
+            arity: arity.into(),
+            function: Box::new(function),
+        });
+        match self.entries.iter().position(|entry| entry.name == name) {
+            Some(index) => self.entries[index] = builtin,
+            None => self.entries.push(builtin),
+        }
+    }
+
+    /// Removes a builtin, so that scripts can no longer reach it. Returns
+    /// whether there was one to remove.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.len() != before
+    }
+
+    /// Makes `puts` print to `output` rather than standard output, here and
+    /// in every copy of this registry.
+    pub fn set_output<W: Write + 'a>(&mut self, output: W) {
+        *self.output.borrow_mut() = Box::new(output);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<Builtin<'a>>> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// The builtin numbered `index`.
+    pub fn get_index(&self, index: usize) -> Option<&Rc<Builtin<'a>>> {
+        self.entries.get(index)
+    }
+
+    /// Every builtin, in the order they are numbered.
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<Builtin<'a>>> {
+        self.entries.iter()
+    }
+}
+
+impl fmt::Debug for Builtins<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|entry| &entry.name))
+            .finish()
+    }
+}
+
+fn len<'a>(arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    match &arguments[0] {
+        Object::String(value) => Ok(Object::Integer(value.chars().count() as i32)),
+        Object::Array(elements) => Ok(Object::Integer(elements.len() as i32)),
+        other => Err(RuntimeError::new(format!(
+            "argument to `len` not supported, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+/// Prints each argument on its own line.
+fn puts<'a>(output: &mut dyn Write, arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    for argument in arguments {
+        writeln!(output, "{}", argument)
+            .map_err(|error| RuntimeError::new(format!("puts failed: {}", error)))?;
+    }
+    Ok(Object::Null)
+}
+
+fn first<'a>(arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    let elements = array_argument("first", &arguments[0])?;
+    Ok(elements.first().cloned().unwrap_or(Object::Null))
+}
+
+fn last<'a>(arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    let elements = array_argument("last", &arguments[0])?;
+    Ok(elements.last().cloned().unwrap_or(Object::Null))
+}
+
+/// Everything but the first element, as a new array.
+fn rest<'a>(arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    match array_argument("rest", &arguments[0])? {
+        [] => Ok(Object::Null),
+        [_, rest @ ..] => Ok(Object::Array(Rc::from(rest))),
+    }
+}
+
+/// A new array with the value appended; the original is left alone.
+fn push<'a>(arguments: &[Object<'a>]) -> Result<Object<'a>, RuntimeError> {
+    let mut pushed = array_argument("push", &arguments[0])?.to_vec();
+    pushed.push(arguments[1].clone());
+    Ok(Object::Array(pushed.into()))
+}
+
+fn array_argument<'o, 'a>(
+    name: &str,
+    argument: &'o Object<'a>,
+) -> Result<&'o [Object<'a>], RuntimeError> {
+    match argument {
+        Object::Array(elements) => Ok(elements),
+        other => Err(RuntimeError::new(format!(
+            "argument to `{}` must be ARRAY, got {}",
+            name,
+            other.type_name()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_and_remove() {
+        let mut builtins = Builtins::standard();
+        assert!(builtins.get("len").is_some());
+
+        builtins.register("double", 1, |arguments: &[Object]| match arguments[0] {
+            Object::Integer(value) => Ok(Object::Integer(value * 2)),
+            _ => Err(RuntimeError::new("double wants an integer")),
+        });
+        let double = builtins.get("double").unwrap().clone();
+        assert_eq!(
+            double.call(&[Object::Integer(21)]).unwrap().to_string(),
+            "42"
+        );
+        assert_eq!(
+            double.call(&[]).unwrap_err().message,
+            "wrong number of arguments: want=1, got=0"
+        );
+
+        assert!(builtins.remove("puts"));
+        assert!(!builtins.remove("puts"));
+        let names: Vec<&str> = builtins
+            .iter()
+            .map(|builtin| builtin.name.as_str())
+            .collect();
+        assert_eq!(names, ["len", "first", "last", "rest", "push", "double"]);
+    }
+}
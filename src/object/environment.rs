@@ -0,0 +1,62 @@
+use super::Object;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Variable bindings for one scope, plus the scope it is nested in.
+///
+/// An `Environment` is a shared handle: clones refer to the same bindings,
+/// which is how functions keep seeing the scope they were defined in.
+#[derive(Clone, Default)]
+pub struct Environment<'a> {
+    scope: Rc<RefCell<Scope<'a>>>,
+}
+
+#[derive(Default)]
+struct Scope<'a> {
+    store: HashMap<Cow<'a, str>, Object<'a>>,
+    outer: Option<Environment<'a>>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new() -> Environment<'a> {
+        Environment::default()
+    }
+
+    /// A new, empty scope whose lookups fall back to `outer`.
+    pub fn enclosed(outer: &Environment<'a>) -> Environment<'a> {
+        Environment {
+            scope: Rc::new(RefCell::new(Scope {
+                store: HashMap::new(),
+                outer: Some(outer.clone()),
+            })),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object<'a>> {
+        let scope = self.scope.borrow();
+        match scope.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => scope.outer.as_ref().and_then(|outer| outer.get(name)),
+        }
+    }
+
+    /// Binds `name` in this scope, shadowing any binding in outer scopes.
+    pub fn set(&self, name: Cow<'a, str>, value: Object<'a>) {
+        self.scope.borrow_mut().store.insert(name, value);
+    }
+}
+
+impl fmt::Debug for Environment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = self.scope.borrow();
+        let mut names: Vec<&str> = scope.store.keys().map(|name| name.as_ref()).collect();
+        names.sort_unstable();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &scope.outer)
+            .finish()
+    }
+}
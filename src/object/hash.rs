@@ -0,0 +1,121 @@
+use super::Object;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The identity of a hashable object: two objects are the same key exactly
+/// when their `HashKey`s are equal. Only integers, booleans and strings can be
+/// keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i32),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+impl HashKey {
+    /// The key for `object`, or an error message naming its type if it can't
+    /// be used as one.
+    pub fn of(object: &Object) -> Result<HashKey, String> {
+        match object {
+            Object::Integer(value) => Ok(HashKey::Integer(*value)),
+            Object::Boolean(value) => Ok(HashKey::Boolean(*value)),
+            Object::String(value) => Ok(HashKey::String(value.clone())),
+            other => Err(format!("unusable as hash key: {}", other.type_name())),
+        }
+    }
+}
+
+/// A hash map value. Pairs remember the order they were first inserted in, so
+/// a hash prints the same way every time.
+#[derive(Debug, Clone, Default)]
+pub struct Hash<'a> {
+    pairs: Vec<(Object<'a>, Object<'a>)>,
+    index: HashMap<HashKey, usize>,
+}
+
+impl<'a> Hash<'a> {
+    pub fn new() -> Hash<'a> {
+        Hash::default()
+    }
+
+    /// Sets `key` to `value`. Fails if the key isn't hashable.
+    pub fn insert(&mut self, key: Object<'a>, value: Object<'a>) -> Result<(), String> {
+        match self.index.entry(HashKey::of(&key)?) {
+            Entry::Occupied(entry) => self.pairs[*entry.get()].1 = value,
+            Entry::Vacant(entry) => {
+                entry.insert(self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks `key` up. Fails if the key isn't hashable.
+    pub fn get(&self, key: &Object<'a>) -> Result<Option<&Object<'a>>, String> {
+        let position = self.index.get(&HashKey::of(key)?);
+        Ok(position.map(|&position| &self.pairs[position].1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn pairs(&self) -> impl Iterator<Item = (&Object<'a>, &Object<'a>)> {
+        self.pairs.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl fmt::Display for Hash<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .pairs()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        write!(f, "{{{}}}", pairs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_keys() {
+        let hello1 = Object::String("Hello World".into());
+        let hello2 = Object::String("Hello World".into());
+        let diff = Object::String("My name is johnny".into());
+        assert_eq!(HashKey::of(&hello1), HashKey::of(&hello2));
+        assert_ne!(HashKey::of(&hello1), HashKey::of(&diff));
+
+        assert_ne!(
+            HashKey::of(&Object::Integer(1)),
+            HashKey::of(&Object::Boolean(true))
+        );
+        assert_eq!(
+            HashKey::of(&Object::Array(Rc::from(Vec::new()))),
+            Err("unusable as hash key: ARRAY".to_string())
+        );
+    }
+
+    #[test]
+    fn insert_keeps_first_position() {
+        let mut hash = Hash::new();
+        hash.insert(Object::Integer(1), Object::Integer(10))
+            .unwrap();
+        hash.insert(Object::Boolean(true), Object::Integer(20))
+            .unwrap();
+        hash.insert(Object::Integer(1), Object::Integer(30))
+            .unwrap();
+
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.to_string(), "{1: 30, true: 20}");
+        assert!(hash.get(&Object::Integer(2)).unwrap().is_none());
+        assert!(hash.insert(Object::Null, Object::Null).is_err());
+    }
+}
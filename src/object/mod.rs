@@ -0,0 +1,161 @@
+pub mod builtins;
+mod environment;
+mod hash;
+
+pub use self::builtins::{Arity, Builtin, Builtins};
+pub use self::environment::Environment;
+pub use self::hash::{Hash, HashKey};
+
+use crate::ast::{BlockStatement, Node};
+use crate::code::{self, Instructions};
+use crate::token::Token;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// How deep Monkey function calls may nest before running stops with a
+/// "stack overflow" error. The evaluator and the VM share it, so a program
+/// recurses as deep under either.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// A runtime value.
+///
+/// Functions keep the parts of the AST they were created from, so objects
+/// borrow from the same source as the program that produced them.
+#[derive(Debug, Clone)]
+pub enum Object<'a> {
+    Integer(i32),
+    Boolean(bool),
+    String(Rc<str>),
+    /// Arrays are immutable, so elements can be shared between arrays.
+    Array(Rc<[Object<'a>]>),
+    Hash(Rc<Hash<'a>>),
+    Null,
+    /// A value on its way out of a function body through `return`.
+    ReturnValue(Box<Object<'a>>),
+    Function(Rc<Function<'a>>),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure<'a>>),
+    Builtin(Rc<Builtin<'a>>),
+    /// A variable closures refer to before it is bound. Only the VM sees it.
+    Cell(Rc<Cell<'a>>),
+}
+
+impl<'a> Object<'a> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Function(_) | Object::CompiledFunction(_) | Object::Closure(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Cell(_) => "CELL",
+        }
+    }
+
+    /// Everything but `false` and `null` counts as true in a condition.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Boolean(false) | Object::Null)
+    }
+}
+
+impl fmt::Display for Object<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => f.write_str(value),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Object::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Hash(hash) => write!(f, "{}", hash),
+            Object::Null => f.write_str("null"),
+            Object::ReturnValue(value) => write!(f, "{}", value),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::CompiledFunction(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure.function),
+            Object::Builtin(builtin) => write!(f, "builtin {}", builtin.name),
+            Object::Cell(cell) => match &*cell.value.borrow() {
+                Some(value) => write!(f, "{}", value),
+                None => write!(f, "unbound {}", cell.name),
+            },
+        }
+    }
+}
+
+/// A function value: a function literal closed over the environment it was
+/// evaluated in.
+pub struct Function<'a> {
+    pub parameters: Rc<[Token<'a>]>,
+    pub body: Rc<BlockStatement<'a>>,
+    pub env: Environment<'a>,
+}
+
+impl fmt::Display for Function<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters: Vec<&str> = self.parameters.iter().map(|p| p.literal.as_ref()).collect();
+        write!(f, "fn({}) {}", parameters.join(", "), self.body.string())
+    }
+}
+
+// NOTE: the environment is left out on purpose; a recursive function is stored
+//       in the environment it closes over, so printing it would never end.
+impl fmt::Debug for Function<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({})", self)
+    }
+}
+
+/// A function lowered to bytecode by the compiler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    /// Slots to reserve on the stack for parameters and `let` bindings.
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    /// Slots of locals that closures capture before they are bound, with their
+    /// names. Each holds a [`Cell`] for as long as the call runs.
+    pub cells: Vec<(usize, Rc<str>)>,
+}
+
+impl fmt::Display for CompiledFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "compiled fn/{}", self.num_parameters)
+    }
+}
+
+impl CompiledFunction {
+    pub fn disassemble(&self) -> String {
+        code::disassemble(&self.instructions)
+    }
+}
+
+/// A variable shared between a function and the closures it creates, so that
+/// a closure sees a `let` that runs after the closure was made.
+#[derive(Debug)]
+pub struct Cell<'a> {
+    pub name: Rc<str>,
+    pub value: RefCell<Option<Object<'a>>>,
+}
+
+impl<'a> Cell<'a> {
+    pub fn new(name: Rc<str>) -> Cell<'a> {
+        Cell {
+            name,
+            value: RefCell::new(None),
+        }
+    }
+}
+
+/// A compiled function together with the values of the variables it captured
+/// from enclosing functions.
+#[derive(Debug, Clone)]
+pub struct Closure<'a> {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Object<'a>>,
+}
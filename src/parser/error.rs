@@ -0,0 +1,101 @@
+use crate::token::{Span, Token, TokenType};
+use std::error::Error;
+use std::fmt;
+
+/// An error reported while parsing a Monkey program. Every variant carries the
+/// offending token, so its location is always available through
+/// [`ParseError::span`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError<'a> {
+    /// A specific token was required but a different one was found.
+    UnexpectedToken { expected: TokenType, got: Token<'a> },
+    /// The token cannot start an expression.
+    NoPrefixParseFn { token: Token<'a> },
+    /// An integer literal that does not fit in the integer type.
+    InvalidIntegerLiteral { token: Token<'a> },
+    /// The lexer could not make sense of the input.
+    IllegalToken { token: Token<'a> },
+    /// A string literal runs to the end of the input.
+    UnterminatedString { token: Token<'a> },
+    /// A string literal has an escape sequence that isn't recognized.
+    InvalidEscape { token: Token<'a> },
+    /// A block comment runs to the end of the input.
+    UnterminatedComment { token: Token<'a> },
+    /// Expressions or blocks are nested deeper than the parser is willing to
+    /// recurse.
+    NestingTooDeep { token: Token<'a> },
+    /// The input ended in the middle of a statement. `expected` is set when a
+    /// specific token was required.
+    UnexpectedEof {
+        expected: Option<TokenType>,
+        token: Token<'a>,
+    },
+}
+
+impl<'a> ParseError<'a> {
+    /// The error for a token the lexer produced in place of one it couldn't
+    /// read, if `token` is one.
+    pub(crate) fn from_lexer(token: &Token<'a>) -> Option<ParseError<'a>> {
+        let token = token.clone();
+        match token.t {
+            TokenType::Illegal => Some(ParseError::IllegalToken { token }),
+            TokenType::UnterminatedString => Some(ParseError::UnterminatedString { token }),
+            TokenType::InvalidEscape => Some(ParseError::InvalidEscape { token }),
+            TokenType::UnterminatedComment => Some(ParseError::UnterminatedComment { token }),
+            _ => None,
+        }
+    }
+
+    pub fn token(&self) -> &Token<'a> {
+        match self {
+            ParseError::UnexpectedToken { got: token, .. }
+            | ParseError::NoPrefixParseFn { token }
+            | ParseError::InvalidIntegerLiteral { token }
+            | ParseError::IllegalToken { token }
+            | ParseError::UnterminatedString { token }
+            | ParseError::InvalidEscape { token }
+            | ParseError::UnterminatedComment { token }
+            | ParseError::NestingTooDeep { token }
+            | ParseError::UnexpectedEof { token, .. } => token,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.token().span
+    }
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, got } => write!(
+                f,
+                "expected next token to be {:?}, got {:?} instead",
+                expected, got.t
+            )?,
+            ParseError::NoPrefixParseFn { token } => {
+                write!(f, "no prefix parse function for {:?} found", token.t)?
+            }
+            ParseError::InvalidIntegerLiteral { token } => {
+                write!(f, "could not parse {} as integer", token.literal)?
+            }
+            ParseError::IllegalToken { token } => write!(f, "illegal token {:?}", token.literal)?,
+            ParseError::UnterminatedString { .. } => write!(f, "unterminated string literal")?,
+            ParseError::InvalidEscape { .. } => {
+                write!(f, "invalid escape sequence in string literal")?
+            }
+            ParseError::UnterminatedComment { .. } => write!(f, "unterminated block comment")?,
+            ParseError::NestingTooDeep { .. } => write!(f, "expression nested too deeply")?,
+            ParseError::UnexpectedEof {
+                expected: Some(expected),
+                ..
+            } => write!(f, "unexpected end of input, expected {:?}", expected)?,
+            ParseError::UnexpectedEof { expected: None, .. } => {
+                write!(f, "unexpected end of input")?
+            }
+        }
+        write!(f, " at {}", self.span())
+    }
+}
+
+impl Error for ParseError<'_> {}
@@ -1,9 +1,27 @@
-use crate::ast::{Expression, Program, Statement};
+mod error;
+
+pub use self::error::ParseError;
+
+use crate::ast::{BlockStatement, Expression, Program, Statement};
 use crate::lexer::Lexer;
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Parses a whole Monkey program, collecting every error found along the way.
+pub fn parse(source: &str) -> Result<Program<'_>, Vec<ParseError<'_>>> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    if parser.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(parser.errors)
+    }
+}
 
-#[derive(PartialOrd, PartialEq)]
-enum Precedence {
+#[derive(PartialOrd, PartialEq, Clone, Copy, Debug)]
+pub(crate) enum Precedence {
     Lowest = 0,
     Equals,
     LessGreater,
@@ -11,9 +29,18 @@ enum Precedence {
     Product,
     Prefix,
     Call,
+    Index,
 }
 
-fn precedence_of_infix_operator(t: TokenType) -> Precedence {
+/// How deeply expressions and blocks may nest before the parser gives up, so
+/// that hostile input can't overflow the stack.
+///
+/// A level takes at most about 5 KiB of stack in a debug build (measured on
+/// nested hash literals, the most expensive), so parsing stays within about
+/// 1.25 MiB of the 2 MiB a spawned thread gets by default.
+const MAX_NESTING_DEPTH: usize = 256;
+
+pub(crate) fn precedence_of_infix_operator(t: TokenType) -> Precedence {
     match t {
         TokenType::Eq => Precedence::Equals,
         TokenType::NotEq => Precedence::Equals,
@@ -23,124 +50,261 @@ fn precedence_of_infix_operator(t: TokenType) -> Precedence {
         TokenType::Minus => Precedence::Sum,
         TokenType::Slash => Precedence::Product,
         TokenType::Asterisk => Precedence::Product,
+        TokenType::LeftParen => Precedence::Call,
+        TokenType::LeftBracket => Precedence::Index,
         _ => Precedence::Lowest,
     }
 }
 
-struct Parser<'a> {
-    lexer: &'a mut Lexer,
+/// Told about every node the parser finishes, for tools that need more than
+/// the AST, such as the [`Cst`](crate::cst::Cst).
+///
+/// Nodes come children first, each with the tokens it covers, counted from the
+/// first token the parser read. Every method does nothing by default.
+pub trait Recorder<'a> {
+    fn statement(&mut self, _statement: &Statement<'a>, _tokens: Range<usize>) {}
 
-    current_token: Option<Token>,
-    peek_token: Option<Token>,
+    fn expression(&mut self, _expression: &Expression<'a>, _tokens: Range<usize>) {}
 
-    errors: Vec<String>,
-}
+    /// Parentheses around an expression, which the AST leaves out. They come
+    /// right after the expression inside.
+    fn parenthesized(&mut self, _tokens: Range<usize>) {}
 
-fn dummy_identifier() -> Expression {
-    Expression::Identifier {
-        token: Token {
-            literal: "".to_string(),
-            t: TokenType::Ident,
-        },
-        value: "".to_string(),
+    fn block(&mut self, _block: &BlockStatement<'a>, _tokens: Range<usize>) {}
+
+    /// A point to [`rollback`](Recorder::rollback) to.
+    fn checkpoint(&self) -> usize {
+        0
     }
+
+    /// Forgets the nodes recorded since `checkpoint`. They belonged to a
+    /// statement that failed to parse, which the AST leaves out.
+    fn rollback(&mut self, _checkpoint: usize) {}
+}
+
+/// Records nothing.
+impl<'a> Recorder<'a> for () {}
+
+/// A Pratt parser pulling tokens from a [`Lexer`], or from any other source of
+/// tokens.
+///
+/// Most callers want [`parse`]; the parser itself is useful when the errors
+/// should be inspected alongside a partially built program.
+///
+/// Tokens and the AST borrow their text from the source the lexer was created
+/// with, so parsing copies no strings.
+pub struct Parser<'a, T = Lexer<'a>, R = ()> {
+    tokens: T,
+
+    current_token: Token<'a>,
+    peek_token: Token<'a>,
+    /// Index of `current_token` among the tokens read.
+    position: usize,
+
+    errors: Vec<ParseError<'a>>,
+    depth: usize,
+    recorder: R,
 }
 
 impl<'a> Parser<'a> {
-    fn new(lexer: &mut Lexer) -> Parser {
-        let mut parser = Parser {
-            lexer,
-            current_token: None,
-            peek_token: None,
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
+        Parser::from_tokens(lexer)
+    }
+}
+
+impl<'a, T: Iterator<Item = Token<'a>>> Parser<'a, T> {
+    /// A parser over tokens lexed beforehand. They don't have to end in EOF.
+    pub fn from_tokens(mut tokens: T) -> Parser<'a, T> {
+        let current_token = tokens.next().unwrap_or_else(|| Token {
+            t: TokenType::EOF,
+            literal: Cow::Borrowed(""),
+            span: Span::default(),
+            comments: Vec::new(),
+        });
+        let peek_token = tokens
+            .next()
+            .unwrap_or_else(|| end_of_input(&current_token));
+        Parser {
+            tokens,
+            current_token,
+            peek_token,
+            position: 0,
             errors: Vec::new(),
-        };
+            depth: 0,
+            recorder: (),
+        }
+    }
 
-        parser.next_token();
-        parser.next_token();
-        parser
+    /// The same parser, telling `recorder` about every node it finishes.
+    pub fn with_recorder<R: Recorder<'a>>(self, recorder: R) -> Parser<'a, T, R> {
+        Parser {
+            tokens: self.tokens,
+            current_token: self.current_token,
+            peek_token: self.peek_token,
+            position: self.position,
+            errors: self.errors,
+            depth: self.depth,
+            recorder,
+        }
     }
+}
 
+impl<'a, T: Iterator<Item = Token<'a>>, R: Recorder<'a>> Parser<'a, T, R> {
     fn next_token(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = Some(self.lexer.next_token().clone());
+        // NOTE: EOF repeats past the end, so the parser can never run past it.
+        let next = match self.tokens.next() {
+            Some(token) => token,
+            None => end_of_input(&self.peek_token),
+        };
+        self.current_token = std::mem::replace(&mut self.peek_token, next);
+        self.position += 1;
     }
 
-    fn parse_program(&mut self) -> Option<Program> {
+    /// Gives back the recorder, once parsing is done.
+    pub fn into_recorder(self) -> R {
+        self.recorder
+    }
+
+    /// The tokens of a node from the token at `start` up to the current token.
+    fn node_tokens(&self, start: usize) -> Range<usize> {
+        start..self.position + 1
+    }
+
+    /// Errors collected so far by [`Parser::parse_program`].
+    pub fn errors(&self) -> &[ParseError<'a>] {
+        &self.errors
+    }
+
+    /// Parses statements until EOF. Statements that fail to parse are left out
+    /// of the program and reported through [`Parser::errors`]; parsing resumes
+    /// at the next statement, so one pass reports every error.
+    ///
+    /// The parser never panics, whatever the input.
+    pub fn parse_program(&mut self) -> Program<'a> {
         let mut program = Program {
             statements: Vec::new(),
         };
 
-        while self.current_token.is_some()
-            && self.current_token.clone().unwrap().t != TokenType::EOF
-        {
-            let statement = self.parse_statement();
-            if let Some(statement) = statement {
+        while !self.current_token_is(TokenType::EOF) {
+            if let Some(statement) = self.parse_statement() {
                 program.statements.push(statement);
+                self.next_token();
+            } else {
+                let failed_at = self.current_token.span;
+                self.synchronize();
+                // NOTE: a `}` without a matching `{` has just been reported, and
+                //       there is no block at the top level for it to close.
+                if self.current_token_is(TokenType::RightBrace)
+                    && self.current_token.span == failed_at
+                {
+                    self.next_token();
+                }
             }
-            self.next_token();
         }
 
-        Some(program)
+        program
     }
 
-    fn parse_statement(&mut self) -> Option<Statement> {
-        match self.current_token {
-            Some(ref token) => match token.t {
-                TokenType::Let => self.parse_let_statement(),
-                TokenType::Return => self.parse_return_statement(),
-                _ => self.parse_expression_statement(),
-            },
-            _ => None,
+    fn parse_statement(&mut self) -> Option<Statement<'a>> {
+        let start = self.position;
+        let checkpoint = self.recorder.checkpoint();
+
+        let statement = match self.current_token.t {
+            TokenType::Let => self.parse_let_statement(),
+            TokenType::Return => self.parse_return_statement(),
+            _ => self.parse_expression_statement(),
+        };
+
+        match &statement {
+            Some(statement) => {
+                let tokens = self.node_tokens(start);
+                self.recorder.statement(statement, tokens);
+            }
+            None => self.recorder.rollback(checkpoint),
         }
+        statement
     }
 
-    fn parse_let_statement(&mut self) -> Option<Statement> {
-        // NOTE: clone -> as_ref로 바꾸면 self에 대한 immutable borrowing이 생기고,
-        //       그 이후 mutable borrowing이 불가능해짐.
-        // 걸리는게 String을 계속 clone하는건데 이거 나중에 &str로 바꿀 수 있지 않을까?
-        // -> 찾아보니까 될 것 같다. chars_indices였나 쓰고 인덱스로 get 메소드 쓰면
-        let let_token = self.current_token.clone().unwrap();
+    /// Skips the rest of a statement that failed to parse, leaving the parser on
+    /// the first token of the next statement, on the `}` closing the enclosing
+    /// block, or on EOF.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.current_token.t {
+                TokenType::EOF => return,
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => return,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::Semicolon if depth == 0 => {
+                    self.next_token();
+                    return;
+                }
+                _ => {}
+            }
+
+            self.next_token();
+
+            if depth == 0
+                && (self.current_token_is(TokenType::Let)
+                    || self.current_token_is(TokenType::Return))
+            {
+                return;
+            }
+        }
+    }
+
+    fn parse_let_statement(&mut self) -> Option<Statement<'a>> {
+        let let_token = self.current_token.clone();
 
         if !self.expect_peek(TokenType::Ident) {
             return None;
         }
 
-        let name_token = self.current_token.clone().unwrap();
+        let name_token = self.current_token.clone();
 
         if !self.expect_peek(TokenType::Assign) {
             return None;
         }
 
-        let assign_token = self.current_token.clone().unwrap();
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
 
-        while !self.current_token_is(TokenType::Semicolon) {
+        // NOTE: optional semicolon
+        if self.peek_token_is(TokenType::Semicolon) {
             self.next_token();
         }
 
         Some(Statement::Let {
             name: name_token,
             token: let_token,
-            value: dummy_identifier(),
+            value,
         })
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
-        let return_token = self.current_token.clone().unwrap();
+    fn parse_return_statement(&mut self) -> Option<Statement<'a>> {
+        let return_token = self.current_token.clone();
+
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
 
-        while !self.current_token_is(TokenType::Semicolon) {
+        // NOTE: optional semicolon
+        if self.peek_token_is(TokenType::Semicolon) {
             self.next_token();
         }
 
         Some(Statement::Return {
             token: return_token,
-            value: dummy_identifier(),
+            value,
         })
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Statement> {
+    fn parse_expression_statement(&mut self) -> Option<Statement<'a>> {
         let statement = Statement::Expression {
-            token: self.current_token.clone().unwrap(),
-            expression: self.parse_expression(Precedence::Lowest).unwrap(),
+            token: self.current_token.clone(),
+            expression: self.parse_expression(Precedence::Lowest)?,
         };
 
         // NOTE: optional semicolon
@@ -150,74 +314,115 @@ impl<'a> Parser<'a> {
         Some(statement)
     }
 
-    fn is_nud(t: TokenType) -> bool {
-        use TokenType::*;
-        match t {
-            Bang | Minus | Ident | Int => true,
-            _ => false,
-        }
-    }
-
     fn is_led(t: TokenType) -> bool {
-        match t {
+        matches!(
+            t,
             TokenType::Plus
-            | TokenType::Minus
-            | TokenType::Slash
-            | TokenType::Asterisk
-            | TokenType::Eq
-            | TokenType::NotEq
-            | TokenType::LT
-            | TokenType::GT => true,
-            _ => false,
-        }
+                | TokenType::Minus
+                | TokenType::Slash
+                | TokenType::Asterisk
+                | TokenType::Eq
+                | TokenType::NotEq
+                | TokenType::LT
+                | TokenType::GT
+                | TokenType::LeftParen
+                | TokenType::LeftBracket
+        )
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
-        if !Self::is_nud(self.current_token.as_ref().unwrap().t) {
-            // TODO: noPrefixParseFnError
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression<'a>> {
+        if !self.enter_nesting() {
             return None;
         }
+        let expression = self.parse_expression_with_nud(precedence);
+        self.depth -= 1;
+        expression
+    }
+
+    /// Goes one level deeper, unless that would be too deep. The caller goes
+    /// back up when it is done.
+    fn enter_nesting(&mut self) -> bool {
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.errors.push(ParseError::NestingTooDeep {
+                token: self.current_token.clone(),
+            });
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
 
-        let mut left_expression = self.parse_nud();
+    fn parse_expression_with_nud(&mut self, precedence: Precedence) -> Option<Expression<'a>> {
+        let start = self.position;
+        // NOTE: the parentheses of a grouped expression have no AST node
+        let grouped = self.current_token_is(TokenType::LeftParen);
+        let mut left_expression = self.parse_nud()?;
+        let tokens = self.node_tokens(start);
+        if grouped {
+            self.recorder.parenthesized(tokens);
+        } else {
+            self.recorder.expression(&left_expression, tokens);
+        }
 
         while !self.peek_token_is(TokenType::Semicolon) && precedence < self.peek_precedence() {
-            if !Self::is_led(self.peek_token.as_ref().unwrap().t) {
-                return left_expression;
+            if !Self::is_led(self.peek_token.t) {
+                return Some(left_expression);
             }
 
             self.next_token();
 
-            left_expression = self.parse_led(left_expression.unwrap());
+            left_expression = self.parse_led(left_expression)?;
+            let tokens = self.node_tokens(start);
+            self.recorder.expression(&left_expression, tokens);
         }
 
-        left_expression
+        Some(left_expression)
     }
 
-    fn parse_nud(&mut self) -> Option<Expression> {
-        // TODO: clone -> as_ref
-        let current_token = self.current_token.clone().unwrap();
+    fn parse_nud(&mut self) -> Option<Expression<'a>> {
+        let current_token = self.current_token.clone();
 
         match current_token.t {
             TokenType::Ident => self.parse_identifier(),
             TokenType::Int => self.parse_integer_literal(),
+            TokenType::True | TokenType::False => self.parse_boolean(),
+            TokenType::String => self.parse_string_literal(),
+            TokenType::LeftBracket => self.parse_array_literal(),
+            // NOTE: blocks are only parsed where a statement list is expected,
+            //       so a `{` starting an expression is always a hash literal
+            TokenType::LeftBrace => self.parse_hash_literal(),
+            TokenType::LeftParen => self.parse_grouped_expression(),
+            TokenType::If => self.parse_if_expression(),
+            TokenType::Function => self.parse_function_literal(),
             TokenType::Bang | TokenType::Minus => {
                 self.next_token();
                 if let Some(right) = self.parse_expression(Precedence::Prefix) {
                     Some(Expression::Prefix {
                         token: current_token.clone(),
-                        operator: current_token.literal.to_string(),
+                        operator: current_token.literal.clone(),
                         right: Box::new(right),
                     })
                 } else {
                     None
                 }
             }
-            _ => panic!("should be nud"),
+            _ => {
+                self.no_prefix_parse_fn_error();
+                None
+            }
         }
     }
 
-    fn parse_led(&mut self, left: Expression) -> Option<Expression> {
-        let token = self.current_token.clone().unwrap();
+    fn parse_led(&mut self, left: Expression<'a>) -> Option<Expression<'a>> {
+        match self.current_token.t {
+            TokenType::LeftParen => self.parse_call_expression(left),
+            TokenType::LeftBracket => self.parse_index_expression(left),
+            _ => self.parse_infix_expression(left),
+        }
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression<'a>) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
         let operator = token.literal.clone();
         let precedence = self.current_precedence(); // TODO; right associativity
         self.next_token();
@@ -225,38 +430,276 @@ impl<'a> Parser<'a> {
             token,
             operator,
             left: Box::new(left),
-            right: Box::new(self.parse_expression(precedence).unwrap()),
+            right: Box::new(self.parse_expression(precedence)?),
         })
     }
 
-    fn parse_identifier(&mut self) -> Option<Expression> {
+    fn parse_identifier(&mut self) -> Option<Expression<'a>> {
         Some(Expression::Identifier {
-            token: self.current_token.clone().unwrap(),
-            value: self.current_token.clone().unwrap().literal,
+            token: self.current_token.clone(),
+            value: self.current_token.literal.clone(),
         })
     }
 
-    fn parse_integer_literal(&mut self) -> Option<Expression> {
-        if let Ok(value) = self.current_token.clone().unwrap().literal.parse() {
+    fn parse_integer_literal(&mut self) -> Option<Expression<'a>> {
+        if let Ok(value) = self.current_token.clone().literal.parse() {
             Some(Expression::IntegerLiteral {
-                token: self.current_token.clone().unwrap(),
+                token: self.current_token.clone(),
                 value,
             })
         } else {
-            self.errors.push(format!(
-                "cloud not parse {} as integer",
-                self.current_token.clone().unwrap().literal
-            ));
+            self.errors.push(ParseError::InvalidIntegerLiteral {
+                token: self.current_token.clone(),
+            });
             None
         }
     }
 
+    fn parse_boolean(&mut self) -> Option<Expression<'a>> {
+        Some(Expression::Boolean {
+            token: self.current_token.clone(),
+            value: self.current_token_is(TokenType::True),
+        })
+    }
+
+    fn parse_string_literal(&mut self) -> Option<Expression<'a>> {
+        Some(Expression::StringLiteral {
+            token: self.current_token.clone(),
+            value: self.current_token.literal.clone(),
+        })
+    }
+
+    fn parse_array_literal(&mut self) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+        let elements = self.parse_expression_list(TokenType::RightBracket)?;
+
+        Some(Expression::ArrayLiteral { token, elements })
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(TokenType::RightBrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            if !self.expect_peek(TokenType::Colon) {
+                return None;
+            }
+
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.peek_token_is(TokenType::RightBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(Expression::HashLiteral { token, pairs })
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<Expression<'a>> {
+        self.next_token();
+
+        let expression = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RightParen) {
+            return None;
+        }
+
+        Some(expression)
+    }
+
+    fn parse_if_expression(&mut self) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+
+        if !self.expect_peek(TokenType::LeftParen) {
+            return None;
+        }
+
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RightParen) {
+            return None;
+        }
+
+        if !self.expect_peek(TokenType::LeftBrace) {
+            return None;
+        }
+
+        let consequence = self.parse_block_statement()?;
+
+        let alternative = if self.peek_token_is(TokenType::Else) {
+            self.next_token();
+
+            if !self.expect_peek(TokenType::LeftBrace) {
+                return None;
+            }
+
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+
+        Some(Expression::If {
+            token,
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: alternative.map(Box::new),
+        })
+    }
+
+    fn parse_block_statement(&mut self) -> Option<BlockStatement<'a>> {
+        if !self.enter_nesting() {
+            return None;
+        }
+        let block = self.parse_block_statements();
+        self.depth -= 1;
+        block
+    }
+
+    fn parse_block_statements(&mut self) -> Option<BlockStatement<'a>> {
+        let start = self.position;
+        let token = self.current_token.clone();
+        let mut statements = Vec::new();
+
+        self.next_token();
+
+        while !self.current_token_is(TokenType::RightBrace) {
+            if self.current_token_is(TokenType::EOF) {
+                self.errors.push(ParseError::UnexpectedEof {
+                    expected: Some(TokenType::RightBrace),
+                    token: self.current_token.clone(),
+                });
+                return None;
+            }
+
+            if let Some(statement) = self.parse_statement() {
+                statements.push(statement);
+                self.next_token();
+            } else {
+                self.synchronize();
+            }
+        }
+
+        let block = BlockStatement { token, statements };
+        let tokens = self.node_tokens(start);
+        self.recorder.block(&block, tokens);
+        Some(block)
+    }
+
+    fn parse_function_literal(&mut self) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+
+        if !self.expect_peek(TokenType::LeftParen) {
+            return None;
+        }
+
+        let parameters = self.parse_function_parameters()?;
+
+        if !self.expect_peek(TokenType::LeftBrace) {
+            return None;
+        }
+
+        let body = self.parse_block_statement()?;
+
+        Some(Expression::FunctionLiteral {
+            token,
+            parameters: parameters.into(),
+            body: Rc::new(body),
+        })
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<Token<'a>>> {
+        let mut parameters = Vec::new();
+
+        if self.peek_token_is(TokenType::RightParen) {
+            self.next_token();
+            return Some(parameters);
+        }
+
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        parameters.push(self.current_token.clone());
+
+        while self.peek_token_is(TokenType::Comma) {
+            self.next_token();
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            parameters.push(self.current_token.clone());
+        }
+
+        if !self.expect_peek(TokenType::RightParen) {
+            return None;
+        }
+
+        Some(parameters)
+    }
+
+    fn parse_call_expression(&mut self, function: Expression<'a>) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+        let arguments = self.parse_expression_list(TokenType::RightParen)?;
+
+        Some(Expression::Call {
+            token,
+            function: Box::new(function),
+            arguments,
+        })
+    }
+
+    /// Parses comma-separated expressions up to and including `end`.
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Expression<'a>>> {
+        let mut list = Vec::new();
+
+        if self.peek_token_is(end) {
+            self.next_token();
+            return Some(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.peek_token_is(TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+
+        Some(list)
+    }
+
+    fn parse_index_expression(&mut self, left: Expression<'a>) -> Option<Expression<'a>> {
+        let token = self.current_token.clone();
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RightBracket) {
+            return None;
+        }
+
+        Some(Expression::Index {
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+        })
+    }
+
     fn current_token_is(&self, t: TokenType) -> bool {
-        return self.current_token.is_some() && self.current_token.clone().unwrap().t == t;
+        self.current_token.t == t
     }
 
     fn peek_token_is(&self, t: TokenType) -> bool {
-        return self.peek_token.is_some() && self.peek_token.clone().unwrap().t == t;
+        self.peek_token.t == t
     }
 
     fn expect_peek(&mut self, t: TokenType) -> bool {
@@ -270,30 +713,68 @@ impl<'a> Parser<'a> {
     }
 
     fn peek_error(&mut self, t: TokenType) {
-        self.errors.push(format!(
-            "expected next Token to be {:?}, got {:?} instead",
-            t,
-            self.peek_token.as_ref().unwrap().t
-        ));
+        let got = self.peek_token.clone();
+        self.errors.push(match got.t {
+            TokenType::EOF => ParseError::UnexpectedEof {
+                expected: Some(t),
+                token: got,
+            },
+            _ => match ParseError::from_lexer(&got) {
+                Some(error) => error,
+                None => ParseError::UnexpectedToken { expected: t, got },
+            },
+        });
+    }
+
+    fn no_prefix_parse_fn_error(&mut self) {
+        let token = self.current_token.clone();
+        self.errors.push(match token.t {
+            TokenType::EOF => ParseError::UnexpectedEof {
+                expected: None,
+                token,
+            },
+            _ => match ParseError::from_lexer(&token) {
+                Some(error) => error,
+                None => ParseError::NoPrefixParseFn { token },
+            },
+        });
     }
 
     fn peek_precedence(&self) -> Precedence {
-        precedence_of_infix_operator(self.peek_token.as_ref().unwrap().t)
+        precedence_of_infix_operator(self.peek_token.t)
     }
 
     fn current_precedence(&self) -> Precedence {
-        precedence_of_infix_operator(self.current_token.as_ref().unwrap().t)
+        precedence_of_infix_operator(self.current_token.t)
+    }
+}
+
+/// An EOF token right after `last`, for token sources that stop without one.
+fn end_of_input<'a>(last: &Token<'a>) -> Token<'a> {
+    if last.t == TokenType::EOF {
+        return last.clone();
+    }
+    Token {
+        t: TokenType::EOF,
+        literal: Cow::Borrowed(""),
+        span: Span {
+            start: last.span.end,
+            end: last.span.end,
+            line: last.span.line,
+            column: last.span.column + last.literal.chars().count(),
+        },
+        comments: Vec::new(),
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::ast::Node;
-    use std::any::Any;
+    use crate::token::Span;
 
     fn check_parser_errors(parser: &Parser) {
-        if parser.errors.len() > 0 {
+        if !parser.errors.is_empty() {
             for ref e in &parser.errors {
                 eprintln!("{}", e);
             }
@@ -303,69 +784,96 @@ mod tests {
 
     #[test]
     fn let_statement() {
-        let input = "
-let x = 5;
-let y = 10;
-let foobar = 838383;
-        ";
-
-        let mut lexer = Lexer::new(input.to_string());
-        let mut parser = Parser::new(&mut lexer);
-        let program = parser.parse_program().unwrap();
-        check_parser_errors(&parser);
-
-        assert_eq!(program.statements.len(), 3);
+        let tests = [
+            ("let x = 5;", "x", "5"),
+            ("let y = 10", "y", "10"),
+            ("let foobar = y;", "foobar", "y"),
+            ("let x = a + b * c;", "x", "(a + (b * c))"),
+        ];
 
-        if let Statement::Let { ref name, .. } = program.statements[0] {
-            assert_eq!(name.literal, "x");
-        } else {
-            panic!("expected let statement");
-        }
+        for (input, expected_name, expected_value) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
 
-        if let Statement::Let { ref name, .. } = program.statements[1] {
-            assert_eq!(name.literal, "y");
-        } else {
-            panic!("expected let statement");
-        }
+            assert_eq!(program.statements.len(), 1);
 
-        if let Statement::Let { ref name, .. } = program.statements[2] {
-            assert_eq!(name.literal, "foobar");
-        } else {
-            panic!("expected let statement");
+            if let Statement::Let {
+                ref name,
+                ref value,
+                ..
+            } = program.statements[0]
+            {
+                assert_eq!(name.literal, expected_name);
+                assert_eq!(value.string(), expected_value);
+            } else {
+                panic!("expected let statement");
+            }
         }
     }
 
     #[test]
     fn return_statement() {
-        let input = "
-return 5;
-return 10;
-return 993322;
-        ";
-
-        let mut lexer = Lexer::new(input.into());
-        let mut parser = Parser::new(&mut lexer);
-        let program = parser.parse_program().unwrap();
-        check_parser_errors(&parser);
+        let tests = [
+            ("return 5;", "5"),
+            ("return x", "x"),
+            ("return 993322 * -y;", "(993322 * (-y))"),
+        ];
+
+        for (input, expected_value) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
 
-        assert_eq!(program.statements.len(), 3);
+            assert_eq!(program.statements.len(), 1);
 
-        for i in 0..3 {
-            if let Statement::Return { ref token, .. } = program.statements[i] {
+            if let Statement::Return {
+                ref token,
+                ref value,
+            } = program.statements[0]
+            {
                 assert_eq!(token.literal, "return");
+                assert_eq!(value.string(), expected_value);
             } else {
                 panic!("expected return statement");
             }
         }
     }
 
+    #[test]
+    fn statement_round_trip() {
+        let tests = [
+            ("let x = a + b * c;", "let x = (a + (b * c));"),
+            ("let x = 5; return x", "let x = 5;return x;"),
+            ("return -a + b;", "return ((-a) + b);"),
+            (
+                "let max = fn(a, b) { if (a > b) { a } else { b } };",
+                "let max = fn(a, b) { if ((a > b)) { a; } else { b; }; };",
+            ),
+        ];
+
+        for (input, expected_string) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.string(), expected_string);
+
+            let printed = program.string();
+            let mut parser = Parser::new(Lexer::new(&printed));
+            let reparsed = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(reparsed.string(), expected_string);
+        }
+    }
+
     #[test]
     fn identifier_expression() {
         let input = "foobar;";
 
-        let mut lexer = Lexer::new(input.into());
-        let mut parser = Parser::new(&mut lexer);
-        let program = parser.parse_program().unwrap();
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
         check_parser_errors(&parser);
 
         assert_eq!(program.statements.len(), 1);
@@ -387,19 +895,18 @@ return 993322;
     fn integer_literal_expression() {
         let input = "55;";
 
-        let mut lexer = Lexer::new(input.into());
-        let mut parser = Parser::new(&mut lexer);
-        let program = parser.parse_program().unwrap();
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
         check_parser_errors(&parser);
 
         assert_eq!(program.statements.len(), 1);
 
-        if let Statement::Expression { ref expression, .. } = &program.statements[0] {
-            if let Expression::IntegerLiteral { value, .. } = expression {
-                if value != &55 {
-                    panic!();
-                }
-            } else {
+        if let Statement::Expression {
+            expression: Expression::IntegerLiteral { value, .. },
+            ..
+        } = &program.statements[0]
+        {
+            if value != &55 {
                 panic!();
             }
         } else {
@@ -407,33 +914,136 @@ return 993322;
         }
     }
 
+    #[test]
+    fn string_literal_expression() {
+        let program = parse(r#""hello\tworld" + "\"q\"";"#).unwrap();
+
+        if let Statement::Expression {
+            expression: Expression::Infix { left, right, .. },
+            ..
+        } = &program.statements[0]
+        {
+            match (left.as_ref(), right.as_ref()) {
+                (
+                    Expression::StringLiteral { value: left, .. },
+                    Expression::StringLiteral { value: right, .. },
+                ) => {
+                    assert_eq!(left, "hello\tworld");
+                    assert_eq!(right, "\"q\"");
+                }
+                other => panic!("expected string literals, got {:?}", other),
+            }
+        } else {
+            panic!("expected infix expression");
+        }
+        assert_eq!(program.string(), r#"("hello\tworld" + "\"q\"");"#);
+    }
+
+    #[test]
+    fn array_literal_expression() {
+        let program = parse("[1, 2 * 2, 3 + 3]; []").unwrap();
+        assert_eq!(program.string(), "[1, (2 * 2), (3 + 3)];[];");
+
+        if let Statement::Expression {
+            expression: Expression::ArrayLiteral { elements, .. },
+            ..
+        } = &program.statements[0]
+        {
+            assert_eq!(elements.len(), 3);
+        } else {
+            panic!("expected array literal");
+        }
+
+        let errors = parse("[1, 2").unwrap_err();
+        if let ParseError::UnexpectedEof { expected, .. } = errors[0] {
+            assert_eq!(expected, Some(TokenType::RightBracket));
+        } else {
+            panic!("expected unexpected EOF, got {:?}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn hash_literal_expression() {
+        let tests = [
+            ("{}", "{};"),
+            (
+                r#"{"one": 1, "two": 2, "three": 3}"#,
+                r#"{"one": 1, "two": 2, "three": 3};"#,
+            ),
+            (r#"{1: true, true: "x",}"#, r#"{1: true, true: "x"};"#),
+            (
+                r#"{"one": 0 + 1, "two": 10 - 8}["two"]"#,
+                r#"({"one": (0 + 1), "two": (10 - 8)}["two"]);"#,
+            ),
+            (
+                "if (x) { {1: 2} } else { {} }",
+                "if (x) { {1: 2}; } else { {}; };",
+            ),
+            ("let f = fn() { {} }", "let f = fn() { {}; };"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).unwrap().string(), expected, "{}", input);
+        }
+
+        let errors = parse(r#"{"a" 1}"#).unwrap_err();
+        if let ParseError::UnexpectedToken { expected, ref got } = errors[0] {
+            assert_eq!(expected, TokenType::Colon);
+            assert_eq!(got.t, TokenType::Int);
+        } else {
+            panic!("expected unexpected token, got {:?}", errors[0]);
+        }
+
+        let errors = parse(r#"{"a": 1 "b": 2}"#).unwrap_err();
+        if let ParseError::UnexpectedToken { expected, .. } = errors[0] {
+            assert_eq!(expected, TokenType::Comma);
+        } else {
+            panic!("expected unexpected token, got {:?}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn index_expression() {
+        let program = parse("myArray[1 + 1]").unwrap();
+
+        if let Statement::Expression {
+            expression: Expression::Index { left, index, .. },
+            ..
+        } = &program.statements[0]
+        {
+            assert_eq!(left.string(), "myArray");
+            assert_eq!(index.string(), "(1 + 1)");
+        } else {
+            panic!("expected index expression");
+        }
+    }
+
     #[test]
     fn prefix_operator() {
-        let tests = vec![("!5;", "!", 5), ("-15;", "-", 15)];
+        let tests = [("!5;", "!", 5), ("-15;", "-", 15)];
 
         for test in tests.iter() {
-            let (ref input, ref expected_operator, expected_value) = test;
+            let (input, ref expected_operator, expected_value) = test;
 
-            let mut lexer = Lexer::new(input.to_string());
-            let mut parser = Parser::new(&mut lexer);
-            let program = parser.parse_program().unwrap();
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
             check_parser_errors(&parser);
 
             assert_eq!(program.statements.len(), 1);
 
-            if let Statement::Expression { ref expression, .. } = program.statements[0] {
-                if let Expression::Prefix {
-                    ref operator,
-                    ref right,
-                    ..
-                } = expression
-                {
-                    assert_eq!(operator, expected_operator);
-                    if let Expression::IntegerLiteral { value, .. } = **right {
-                        assert_eq!(value, *expected_value);
-                    } else {
-                        panic!();
-                    }
+            if let Statement::Expression {
+                expression:
+                    Expression::Prefix {
+                        ref operator,
+                        ref right,
+                        ..
+                    },
+                ..
+            } = program.statements[0]
+            {
+                assert_eq!(operator, expected_operator);
+                if let Expression::IntegerLiteral { value, .. } = **right {
+                    assert_eq!(value, *expected_value);
                 } else {
                     panic!();
                 }
@@ -456,35 +1066,32 @@ return 993322;
             ("5 != 5;", 5, "!=", 5),
         ];
 
-        for (ref input, expected_left_operand, ref expected_operator, expected_right_operand) in
-            tests
-        {
-            let mut lexer = Lexer::new(input.to_string());
-            let mut parser = Parser::new(&mut lexer);
-            let program = parser.parse_program().unwrap();
+        for (input, expected_left_operand, ref expected_operator, expected_right_operand) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
             check_parser_errors(&parser);
 
             assert_eq!(program.statements.len(), 1);
 
-            if let Statement::Expression { ref expression, .. } = program.statements[0] {
-                if let Expression::Infix {
-                    ref operator,
-                    ref left,
-                    ref right,
-                    ..
-                } = expression
-                {
-                    assert_eq!(operator, expected_operator);
-                    if let Expression::IntegerLiteral { value, .. } = **left {
-                        assert_eq!(value, expected_left_operand);
-                    } else {
-                        panic!();
-                    }
-                    if let Expression::IntegerLiteral { value, .. } = **right {
-                        assert_eq!(value, expected_right_operand);
-                    } else {
-                        panic!();
-                    }
+            if let Statement::Expression {
+                expression:
+                    Expression::Infix {
+                        ref operator,
+                        ref left,
+                        ref right,
+                        ..
+                    },
+                ..
+            } = program.statements[0]
+            {
+                assert_eq!(operator, expected_operator);
+                if let Expression::IntegerLiteral { value, .. } = **left {
+                    assert_eq!(value, expected_left_operand);
+                } else {
+                    panic!();
+                }
+                if let Expression::IntegerLiteral { value, .. } = **right {
+                    assert_eq!(value, expected_right_operand);
                 } else {
                     panic!();
                 }
@@ -512,15 +1119,516 @@ return 993322;
                 "3 + 4 * 5 == 3 * 1 + 4 * 5",
                 "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)));",
             ),
+            ("true", "true;"),
+            ("false", "false;"),
+            ("3 > 5 == false", "((3 > 5) == false);"),
+            ("3 < 5 == true", "((3 < 5) == true);"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4);"),
+            ("(5 + 5) * 2", "((5 + 5) * 2);"),
+            ("2 / (5 + 5)", "(2 / (5 + 5));"),
+            ("-(5 + 5)", "(-(5 + 5));"),
+            ("!(true == true)", "(!(true == true));"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d);"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)));",
+            ),
+            (
+                "add(a + b + c * d / f + g)",
+                "add((((a + b) + ((c * d) / f)) + g));",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d);",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])));",
+            ),
+            ("f(x)[0]", "(f(x)[0]);"),
+            ("makeAdder(2)(3)", "makeAdder(2)(3);"),
+            ("fn(x) { x }(5)", "fn(x) { x; }(5);"),
+            ("-a[0]", "(-(a[0]));"),
         ];
-        for (ref input, ref expected_string) in tests {
-            let mut lexer = Lexer::new(input.to_string());
-            let mut parser = Parser::new(&mut lexer);
-            let program = parser.parse_program().unwrap();
+        for (input, ref expected_string) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
             check_parser_errors(&parser);
 
             assert_eq!(&program.string(), expected_string);
         }
     }
 
+    #[test]
+    fn boolean_expression() {
+        let tests = [("true;", true), ("false;", false)];
+
+        for (input, expected_value) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+
+            if let Statement::Expression {
+                expression: Expression::Boolean { value, .. },
+                ..
+            } = program.statements[0]
+            {
+                assert_eq!(value, expected_value);
+            } else {
+                panic!("expected boolean");
+            }
+        }
+    }
+
+    #[test]
+    fn if_expression() {
+        let tests = [
+            ("if (x < y) { x }", "(x < y)", "{ x; }", None),
+            (
+                "if (x < y) { x } else { y; z }",
+                "(x < y)",
+                "{ x; }",
+                Some("{ y; z; }"),
+            ),
+            ("if (x) {} else {}", "x", "{}", Some("{}")),
+        ];
+
+        for (input, expected_condition, expected_consequence, expected_alternative) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+
+            if let Statement::Expression {
+                expression:
+                    Expression::If {
+                        ref condition,
+                        ref consequence,
+                        ref alternative,
+                        ..
+                    },
+                ..
+            } = program.statements[0]
+            {
+                assert_eq!(condition.string(), expected_condition);
+                assert_eq!(consequence.string(), expected_consequence);
+                assert_eq!(
+                    alternative.as_deref().map(Node::string).as_deref(),
+                    expected_alternative
+                );
+            } else {
+                panic!("expected if expression");
+            }
+        }
+    }
+
+    #[test]
+    fn function_literal() {
+        let tests = [
+            ("fn() {};", vec![], "{}"),
+            ("fn(x) { x };", vec!["x"], "{ x; }"),
+            (
+                "fn(x, y, z) { x + y; z }",
+                vec!["x", "y", "z"],
+                "{ (x + y); z; }",
+            ),
+        ];
+
+        for (input, expected_parameters, expected_body) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+
+            if let Statement::Expression {
+                expression:
+                    Expression::FunctionLiteral {
+                        ref parameters,
+                        ref body,
+                        ..
+                    },
+                ..
+            } = program.statements[0]
+            {
+                let parameters: Vec<&str> = parameters.iter().map(|p| p.literal.as_ref()).collect();
+                assert_eq!(parameters, expected_parameters);
+                assert_eq!(body.string(), expected_body);
+            } else {
+                panic!("expected function literal");
+            }
+        }
+    }
+
+    #[test]
+    fn call_expression() {
+        let tests = [
+            ("add();", "add", vec![]),
+            ("add(1);", "add", vec!["1"]),
+            (
+                "add(1, 2 * 3, 4 + 5);",
+                "add",
+                vec!["1", "(2 * 3)", "(4 + 5)"],
+            ),
+            ("fn(x) { x }(5)", "fn(x) { x; }", vec!["5"]),
+        ];
+
+        for (input, expected_function, expected_arguments) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+
+            if let Statement::Expression {
+                expression:
+                    Expression::Call {
+                        ref function,
+                        ref arguments,
+                        ..
+                    },
+                ..
+            } = program.statements[0]
+            {
+                let arguments: Vec<String> = arguments.iter().map(Node::string).collect();
+                assert_eq!(function.string(), expected_function);
+                assert_eq!(arguments, expected_arguments);
+            } else {
+                panic!("expected call expression");
+            }
+        }
+    }
+
+    #[test]
+    fn full_program() {
+        // The lexer sample, minus its `!-/*5;` line which only exercises operator lexing.
+        let input = "let five = 5;
+let ten = 10;
+
+let add = fn(x, y) {
+  x + y;
+};
+
+let result = add(five, ten);
+5 < 10 > 5;
+
+if (5 < 10) {
+	return true;
+} else {
+	return false;
+}
+
+10 == 10;
+10 != 9;";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+
+        let statements: Vec<String> = program.statements.iter().map(Node::string).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "let five = 5;",
+                "let ten = 10;",
+                "let add = fn(x, y) { (x + y); };",
+                "let result = add(five, ten);",
+                "((5 < 10) > 5);",
+                "if ((5 < 10)) { return true; } else { return false; };",
+                "(10 == 10);",
+                "(10 != 9);",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_entry_point() {
+        let program = parse("let x = 1; x + 2").unwrap();
+        assert_eq!(program.string(), "let x = 1;(x + 2);");
+
+        let errors = parse("let x 5; let y 10;").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ParseError::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected next token to be Assign, got Int instead at 1:7",
+                "expected next token to be Assign, got Int instead at 1:16",
+            ]
+        );
+    }
+
+    #[test]
+    fn error_kinds() {
+        let errors = parse("let x = 99999999999").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        if let ParseError::InvalidIntegerLiteral { ref token } = errors[0] {
+            assert_eq!(token.literal, "99999999999");
+            assert_eq!(
+                errors[0].span(),
+                Span {
+                    start: 8,
+                    end: 19,
+                    line: 1,
+                    column: 9
+                }
+            );
+        } else {
+            panic!("expected invalid integer literal, got {:?}", errors[0]);
+        }
+
+        let errors = parse("let x = 1;\nlet y = fn(a, b {};").unwrap_err();
+        if let ParseError::UnexpectedToken { expected, ref got } = errors[0] {
+            assert_eq!(expected, TokenType::RightParen);
+            assert_eq!(got.t, TokenType::LeftBrace);
+            assert_eq!(
+                errors[0].span(),
+                Span {
+                    start: 27,
+                    end: 28,
+                    line: 2,
+                    column: 17
+                }
+            );
+        } else {
+            panic!("expected unexpected token, got {:?}", errors[0]);
+        }
+
+        let errors = parse("let x").unwrap_err();
+        if let ParseError::UnexpectedEof { expected, .. } = errors[0] {
+            assert_eq!(expected, Some(TokenType::Assign));
+        } else {
+            panic!("expected unexpected EOF, got {:?}", errors[0]);
+        }
+
+        let errors = parse("let x = 1;\nlet s = \"abc;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "unterminated string literal at 2:9");
+
+        let errors = parse("let s = \"a\\qb\"; let").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "invalid escape sequence in string literal at 1:9"
+        );
+
+        let errors = parse("let x = 1; /* a /* nested */ comment").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "unterminated block comment at 1:12");
+    }
+
+    #[test]
+    fn error_recovery() {
+        let mut parser = Parser::new(Lexer::new("let x = ; let y = 2; 3 + ; let 5 = z; w"));
+        let program = parser.parse_program();
+
+        assert_eq!(program.string(), "let y = 2;w;");
+        let messages: Vec<String> = parser.errors().iter().map(ParseError::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "no prefix parse function for Semicolon found at 1:9",
+                "no prefix parse function for Semicolon found at 1:26",
+                "expected next token to be Ident, got Int instead at 1:32",
+            ]
+        );
+    }
+
+    #[test]
+    fn error_recovery_in_blocks() {
+        let mut parser = Parser::new(Lexer::new(
+            "let f = fn() { let = 1; if (x) { y + } ; z }; f",
+        ));
+        let program = parser.parse_program();
+
+        assert_eq!(program.string(), "let f = fn() { if (x) {}; z; };f;");
+        assert_eq!(parser.errors().len(), 2);
+
+        let mut parser = Parser::new(Lexer::new("let f = fn(a, b { a }; } f"));
+        let program = parser.parse_program();
+
+        assert_eq!(program.string(), "f;");
+        let messages: Vec<String> = parser.errors().iter().map(ParseError::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected next token to be RightParen, got LeftBrace instead at 1:17",
+                "no prefix parse function for RightBrace found at 1:24",
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let tests = [
+            ")",
+            ";",
+            "}",
+            "let",
+            "let x",
+            "let x =",
+            "return",
+            "fn(",
+            "fn(x,",
+            "fn(x) {",
+            "if (",
+            "if (x) { 1 } else",
+            "add(1, ",
+            "((((",
+            "!",
+            "1 +",
+            "a(b(c(",
+        ];
+
+        for input in tests {
+            let errors = parse(input).unwrap_err();
+            assert!(!errors.is_empty(), "expected errors for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = format!(
+            "{}1{}",
+            "(".repeat(MAX_NESTING_DEPTH - 1),
+            ")".repeat(MAX_NESTING_DEPTH - 1)
+        );
+        assert!(parse(&nested).is_ok());
+
+        let nested = format!("{}1{}; x", "(".repeat(1_000), ")".repeat(1_000));
+        let mut parser = Parser::new(Lexer::new(&nested));
+        let program = parser.parse_program();
+
+        assert_eq!(program.string(), "x;");
+        assert!(matches!(
+            parser.errors(),
+            [ParseError::NestingTooDeep { .. }]
+        ));
+    }
+
+    #[test]
+    fn nesting_limit_fits_the_stack() {
+        // NOTE: tests run on spawned threads, so this checks the 2 MiB a
+        //       spawned thread gets; the last number is the levels per repeat
+        let shapes = [
+            ("(", ")", 1),
+            ("-", "", 1),
+            ("[", "]", 1),
+            ("{1: ", "}", 1),
+            ("f(", ")", 1),
+            ("a[", "]", 1),
+            ("1 + (", ")", 2),
+            ("fn() { ", " }", 2),
+            ("if (x) { ", " }", 2),
+        ];
+
+        for (open, close, levels) in shapes {
+            let repeats = (MAX_NESTING_DEPTH - 1) / levels;
+            let nested = format!("{}1{}", open.repeat(repeats), close.repeat(repeats));
+            let program = parse(&nested).unwrap();
+            assert!(!program.string().is_empty(), "{}", open);
+
+            let repeats = repeats + 1;
+            let nested = format!("{}1{}", open.repeat(repeats), close.repeat(repeats));
+            let errors = parse(&nested).unwrap_err();
+            assert!(
+                matches!(errors[0], ParseError::NestingTooDeep { .. }),
+                "{}",
+                open
+            );
+        }
+    }
+
+    /// A small xorshift generator, so the fuzz tests are reproducible without
+    /// pulling in a dependency.
+    pub(crate) struct Rng(pub(crate) u64);
+
+    impl Rng {
+        pub(crate) fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        pub(crate) fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn assert_parses_without_panic(input: &str) {
+        let mut parser = Parser::new(Lexer::new(input));
+        parser.parse_program();
+        for error in parser.errors() {
+            let span = error.span();
+            assert!(span.start <= span.end && span.end <= input.len());
+        }
+    }
+
+    #[test]
+    fn fuzz_arbitrary_bytes() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let len = rng.below(64);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            assert_parses_without_panic(&String::from_utf8_lossy(&bytes));
+        }
+    }
+
+    /// Words the token soup fuzz tests are made of.
+    pub(crate) const VOCABULARY: &[&str] = &[
+        "let",
+        "return",
+        "fn",
+        "if",
+        "else",
+        "true",
+        "false",
+        "x",
+        "y",
+        "1",
+        "99999999999",
+        "=",
+        "+",
+        "-",
+        "!",
+        "*",
+        "/",
+        "<",
+        ">",
+        "==",
+        "!=",
+        ",",
+        ";",
+        "(",
+        ")",
+        "{",
+        "}",
+        "[",
+        "]",
+        ":",
+        "é",
+        "@",
+        "\"",
+        "\"s\"",
+        "\\",
+        "\\u{",
+        "//",
+        "/*",
+        "*/",
+    ];
+
+    #[test]
+    fn fuzz_token_soup() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let len = rng.below(48);
+            let words: Vec<&str> = (0..len)
+                .map(|_| VOCABULARY[rng.below(VOCABULARY.len())])
+                .collect();
+            assert_parses_without_panic(&words.join(" "));
+            assert_parses_without_panic(&words.concat());
+        }
+    }
 }
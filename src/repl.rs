@@ -0,0 +1,214 @@
+//! The read-eval-print loop behind the `monkey_rs` binary.
+
+use crate::ast::Statement;
+use crate::eval::Interpreter;
+use crate::lexer::Lexer;
+use crate::parser::parse;
+use crate::token::TokenType;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+pub const PROMPT: &str = ">> ";
+/// Shown instead of [`PROMPT`] while the input so far is incomplete.
+pub const CONTINUATION_PROMPT: &str = ".. ";
+
+/// Reads programs from `input` and writes their results to `output`, until
+/// `input` runs out.
+///
+/// A program normally ends with its line. Input that is still incomplete (see
+/// [`is_incomplete`]) is continued on the next line instead; an empty line
+/// gives up on it and evaluates what is there.
+///
+/// Every program is evaluated in the same environment, so bindings carry over
+/// from one to the next. Errors are reported and the loop goes on. What `puts`
+/// prints goes to `output` as well.
+pub fn start<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut output = SharedOutput(Rc::new(RefCell::new(output)));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    let mut source = String::new();
+
+    loop {
+        let prompt = if source.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        let start = source.len();
+        let finished = input.read_line(&mut source)? == 0;
+        let gave_up = start > 0 && source[start..].trim().is_empty();
+        if !(finished || gave_up) && is_incomplete(&source) {
+            continue;
+        }
+
+        if !source.trim().is_empty() {
+            evaluate(&mut interpreter, &source, &mut output)?;
+        }
+        source.clear();
+
+        if finished {
+            writeln!(output)?;
+            return Ok(());
+        }
+    }
+}
+
+/// Whether `source` stops partway through a statement: inside an unclosed
+/// `(`, `{` or `[`, inside a string or block comment, or right after an
+/// operator.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut last = TokenType::EOF;
+
+    for token in Lexer::new(source) {
+        match token.t {
+            TokenType::EOF => break,
+            TokenType::UnterminatedString | TokenType::UnterminatedComment => return true,
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => {}
+        }
+        last = token.t;
+    }
+
+    depth > 0
+        || matches!(
+            last,
+            TokenType::Assign
+                | TokenType::Plus
+                | TokenType::Minus
+                | TokenType::Bang
+                | TokenType::Asterisk
+                | TokenType::Slash
+                | TokenType::LT
+                | TokenType::GT
+                | TokenType::Eq
+                | TokenType::NotEq
+                | TokenType::Comma
+                | TokenType::Colon
+        )
+}
+
+/// The REPL's output, shared with the interpreter for `puts`.
+struct SharedOutput<W>(Rc<RefCell<W>>);
+
+impl<W> Clone for SharedOutput<W> {
+    fn clone(&self) -> Self {
+        SharedOutput(self.0.clone())
+    }
+}
+
+impl<W: Write> Write for SharedOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+fn evaluate<W: Write>(
+    interpreter: &mut Interpreter,
+    source: &str,
+    output: &mut W,
+) -> io::Result<()> {
+    // NOTE: values outlive the input that created them (a function keeps its
+    //       body), so the program is copied out of the input
+    let program = match parse(source) {
+        Ok(program) => program.into_owned(),
+        Err(errors) => {
+            for error in errors {
+                writeln!(output, "error: {}", error)?;
+            }
+            return Ok(());
+        }
+    };
+
+    // A `let` has no value worth showing.
+    let quiet = matches!(
+        program.statements.last(),
+        None | Some(Statement::Let { .. })
+    );
+    match interpreter.eval(&program) {
+        Ok(_) if quiet => {}
+        Ok(value) => writeln!(output, "{}", value)?,
+        Err(error) => writeln!(output, "error: {}", error)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> String {
+        let mut output = Vec::new();
+        start(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn bindings_persist() {
+        let input = "let add = fn(a, b) { a + b };\nlet x = 2;\nadd(x, 3)\n\n\"done\"\n";
+        assert_eq!(run(input), ">> >> >> 5\n>> >> done\n>> \n");
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_loop() {
+        let input = "let x = ;\n5 + true\nfoo\nlet y = 1; y\n";
+        assert_eq!(
+            run(input),
+            ">> error: no prefix parse function for Semicolon found at 1:9\n\
+             >> error: type mismatch: INTEGER + BOOLEAN at 1:3\n\
+             >> error: identifier not found: foo at 1:1\n\
+             >> 1\n\
+             >> \n"
+        );
+    }
+
+    #[test]
+    fn puts_writes_to_the_output() {
+        let input = "let greet = fn(name) { puts(\"hi \" + name, 1) };\ngreet(\"you\")\n";
+        assert_eq!(run(input), ">> >> hi you\n1\nnull\n>> \n");
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let tests = [
+            ("let add = fn(x, y) {", true),
+            ("add(1,", true),
+            ("[1, 2", true),
+            ("{\"a\":", true),
+            ("let x = 1 +", true),
+            ("let x =", true),
+            ("\"unterminated", true),
+            ("1 /* comment", true),
+            ("1 // comment", false),
+            ("\"ok\" + \"ok\"", false),
+            ("let x = 1;", false),
+            ("fn(x) { x }(1))", false),
+            ("", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(is_incomplete(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn multi_line_input() {
+        let input = "let add = fn(x, y) {\n  x +\n    y\n};\nadd(1,\n2)\n\"a\nb\"\n";
+        assert_eq!(run(input), ">> .. .. .. >> .. 3\n>> .. a\nb\n>> \n");
+
+        let input = "(1 +\n\n2\n";
+        assert_eq!(
+            run(input),
+            ">> .. error: unexpected end of input at 3:1\n>> 2\n>> \n"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Running a whole program at once, as `monkey_rs run` does.
+
+use crate::eval::Interpreter;
+use crate::parser::parse;
+use std::io::{self, Write};
+
+/// Parses and evaluates `source`, writing any errors to `errors` prefixed with
+/// `name`. Returns whether the script ran to the end.
+///
+/// Unlike the REPL, the value of the last statement isn't printed; scripts
+/// produce output through `puts`.
+pub fn run<W: Write>(name: &str, source: &str, errors: &mut W) -> io::Result<bool> {
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(parse_errors) => {
+            for error in parse_errors {
+                writeln!(errors, "{}: error: {}", name, error)?;
+            }
+            return Ok(false);
+        }
+    };
+
+    match Interpreter::new().eval(&program) {
+        Ok(_) => Ok(true),
+        Err(error) => {
+            writeln!(errors, "{}: error: {}", name, error)?;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_run(source: &str) -> (bool, String) {
+        let mut errors = Vec::new();
+        let ok = run("script.mk", source, &mut errors).unwrap();
+        (ok, String::from_utf8(errors).unwrap())
+    }
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(
+            test_run("#!/usr/bin/env monkey_rs\nlet x = 1;\nx + 1;\n"),
+            (true, String::new())
+        );
+        assert_eq!(
+            test_run("let x = 1;\nlet = 2;\nlet y = ;"),
+            (
+                false,
+                "script.mk: error: expected next token to be Ident, got Assign instead at 2:5\n\
+                 script.mk: error: no prefix parse function for Semicolon found at 3:9\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            test_run("let f = fn(x) {\n  x + true\n};\nf(1);\n"),
+            (
+                false,
+                "script.mk: error: type mismatch: INTEGER + BOOLEAN at 2:5\n    \
+                 in f (called at 4:1)\n"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -1,11 +1,23 @@
+use std::borrow::Cow;
+use std::fmt;
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum TokenType {
     Illegal,
+    /// A string literal missing its closing quote; runs to the end of input.
+    UnterminatedString,
+    /// A string literal containing an escape sequence the lexer doesn't know.
+    InvalidEscape,
+    /// A `/*` comment missing its closing `*/`; runs to the end of input.
+    UnterminatedComment,
     EOF,
 
     // Identifiers + literals
     Ident,
     Int,
+    /// A string literal. The token's literal is the decoded contents, without
+    /// the quotes; its span covers the quotes.
+    String,
 
     // Operators
     Assign,
@@ -24,11 +36,14 @@ pub enum TokenType {
     // Delimiters
     Comma,
     Semicolon,
+    Colon,
 
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
 
     // Keywords
     Function,
@@ -40,10 +55,67 @@ pub enum TokenType {
     Return,
 }
 
+/// Where a token sits in the source.
+///
+/// `start..end` is the byte range of the token, so `&source[span.start..span.end]`
+/// is its text. `line` and `column` locate `start`; both are 1-based and columns
+/// count characters, not bytes.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A token and its text.
+///
+/// The lexer borrows `literal` straight from the source, so tokens are cheap to
+/// clone; an owned literal is only needed for tokens that don't come from a
+/// source string, or that have to outlive it (see [`Token::into_owned`]).
 #[derive(Eq, PartialEq, Debug, Clone)]
-pub struct Token {
+pub struct Token<'a> {
     pub t: TokenType,
-    pub literal: String,
+    pub literal: Cow<'a, str>,
+    pub span: Span,
+    /// The comments between the previous token and this one. Always empty
+    /// unless the lexer was made with
+    /// [`Lexer::with_comments`](crate::lexer::Lexer::with_comments).
+    pub comments: Vec<Comment<'a>>,
+}
+
+impl<'a> Token<'a> {
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            t: self.t,
+            literal: Cow::Owned(self.literal.into_owned()),
+            span: self.span,
+            comments: self.comments.into_iter().map(Comment::into_owned).collect(),
+        }
+    }
+}
+
+/// A `// line` or `/* block */` comment. `text` includes the delimiters, but
+/// not the newline ending a line comment.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Comment<'a> {
+    pub text: Cow<'a, str>,
+    pub span: Span,
+}
+
+impl<'a> Comment<'a> {
+    pub fn into_owned(self) -> Comment<'static> {
+        Comment {
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+        }
+    }
 }
 
 pub fn lookup_ident(ident: &str) -> TokenType {
@@ -0,0 +1,714 @@
+use crate::code::{read_u16, Opcode};
+use crate::compiler::Bytecode;
+use crate::object::{Builtins, Cell, Closure, CompiledFunction, Hash, Object, MAX_CALL_DEPTH};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+/// Room for 64 values a frame, more than calls take in practice, so deep
+/// recursion stops at the frame limit, the same place as in the evaluator.
+const STACK_SIZE: usize = 64 * MAX_CALL_DEPTH;
+
+/// An error raised while running bytecode. Messages match the ones the
+/// evaluator reports for the same program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub message: String,
+}
+
+impl VmError {
+    fn new(message: String) -> VmError {
+        VmError { message }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for VmError {}
+
+/// A function call in progress.
+struct Frame {
+    closure: Rc<Closure<'static>>,
+    ip: usize,
+    /// Stack index of the first local; arguments come first.
+    base_pointer: usize,
+}
+
+/// A stack machine that runs [`Bytecode`] produced by the
+/// [`Compiler`](crate::compiler::Compiler).
+pub struct Vm {
+    constants: Vec<Object<'static>>,
+    builtins: Builtins<'static>,
+    stack: Vec<Object<'static>>,
+    /// Unset until the `let` binding the global runs.
+    globals: Vec<Option<Object<'static>>>,
+    global_names: Vec<String>,
+    frames: Vec<Frame>,
+    last_popped: Option<Object<'static>>,
+}
+
+impl Vm {
+    /// A VM for bytecode compiled with the standard builtins.
+    pub fn new(bytecode: Bytecode) -> Vm {
+        Vm::with_builtins(bytecode, Builtins::standard())
+    }
+
+    /// A VM for bytecode compiled with
+    /// [`Compiler::with_builtins`](crate::compiler::Compiler::with_builtins).
+    pub fn with_builtins(bytecode: Bytecode, builtins: Builtins<'static>) -> Vm {
+        let main = CompiledFunction {
+            instructions: bytecode.instructions,
+            num_locals: 0,
+            num_parameters: 0,
+            cells: Vec::new(),
+        };
+        Vm {
+            constants: bytecode.constants,
+            builtins,
+            stack: Vec::new(),
+            globals: Vec::new(),
+            global_names: bytecode.globals,
+            frames: vec![Frame {
+                closure: Rc::new(Closure {
+                    function: Rc::new(main),
+                    free: Vec::new(),
+                }),
+                ip: 0,
+                base_pointer: 0,
+            }],
+            last_popped: None,
+        }
+    }
+
+    /// The value of the last expression statement run, which is the result of
+    /// the program.
+    pub fn last_popped_stack_elem(&self) -> Option<&Object<'static>> {
+        self.last_popped.as_ref()
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let instructions = &frame.closure.function.instructions;
+            if frame.ip >= instructions.len() {
+                return Ok(());
+            }
+
+            let op = Opcode::from_byte(instructions[frame.ip]).ok_or_else(|| {
+                VmError::new(format!("unknown opcode {}", instructions[frame.ip]))
+            })?;
+            let operand_start = frame.ip + 1;
+            frame.ip += 1 + op.definition().operand_widths.iter().sum::<usize>();
+
+            match op {
+                Opcode::Constant => {
+                    let index = self.read_u16_operand(operand_start);
+                    self.push(self.constants[index].clone())?;
+                }
+                Opcode::Pop => {
+                    self.last_popped = Some(self.pop());
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::GreaterThan
+                | Opcode::LessThan => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = execute_binary_operation(op, left, right)?;
+                    self.push(result)?;
+                }
+                Opcode::True => self.push(Object::Boolean(true))?,
+                Opcode::False => self.push(Object::Boolean(false))?,
+                Opcode::Null => self.push(Object::Null)?,
+                Opcode::Minus => match self.pop() {
+                    Object::Integer(value) => self.push(Object::Integer(value.wrapping_neg()))?,
+                    other => {
+                        let message = format!("unknown operator: -{}", other.type_name());
+                        return Err(VmError::new(message));
+                    }
+                },
+                Opcode::Bang => {
+                    let operand = self.pop();
+                    self.push(Object::Boolean(!operand.is_truthy()))?;
+                }
+                Opcode::JumpNotTruthy => {
+                    let target = self.read_u16_operand(operand_start);
+                    if !self.pop().is_truthy() {
+                        self.current_frame().ip = target;
+                    }
+                }
+                Opcode::Jump => {
+                    let target = self.read_u16_operand(operand_start);
+                    self.current_frame().ip = target;
+                }
+                Opcode::SetGlobal => {
+                    let index = self.read_u16_operand(operand_start);
+                    let value = self.pop();
+                    if index >= self.globals.len() {
+                        self.globals.resize(index + 1, None);
+                    }
+                    self.globals[index] = Some(value);
+                }
+                Opcode::GetGlobal => {
+                    let index = self.read_u16_operand(operand_start);
+                    match self.globals.get(index).cloned().flatten() {
+                        Some(value) => self.push(value)?,
+                        None => {
+                            let name = self.global_names.get(index).map_or("", String::as_str);
+                            let message = format!("identifier not found: {}", name);
+                            return Err(VmError::new(message));
+                        }
+                    }
+                }
+                Opcode::SetLocal => {
+                    let index = self.read_u8_operand(operand_start);
+                    let base_pointer = self.current_frame().base_pointer;
+                    self.stack[base_pointer + index] = self.pop();
+                }
+                Opcode::GetLocal => {
+                    let index = self.read_u8_operand(operand_start);
+                    let base_pointer = self.current_frame().base_pointer;
+                    self.push(self.stack[base_pointer + index].clone())?;
+                }
+                Opcode::GetBuiltin => {
+                    let index = self.read_u8_operand(operand_start);
+                    let builtin = self
+                        .builtins
+                        .get_index(index)
+                        .cloned()
+                        .ok_or_else(|| VmError::new(format!("unknown builtin {}", index)))?;
+                    self.push(Object::Builtin(builtin))?;
+                }
+                Opcode::Array => {
+                    let length = self.read_u16_operand(operand_start);
+                    let elements = self.stack.split_off(self.stack.len() - length);
+                    self.push(Object::Array(elements.into()))?;
+                }
+                Opcode::Hash => {
+                    let length = self.read_u16_operand(operand_start);
+                    let elements = self.stack.split_off(self.stack.len() - length);
+                    let mut hash = Hash::new();
+                    let mut elements = elements.into_iter();
+                    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                        hash.insert(key, value).map_err(VmError::new)?;
+                    }
+                    self.push(Object::Hash(Rc::new(hash)))?;
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    let result = execute_index_expression(left, index)?;
+                    self.push(result)?;
+                }
+                Opcode::Closure => {
+                    let index = self.read_u16_operand(operand_start);
+                    let num_free = self.read_u8_operand(operand_start + 2);
+                    let function = match &self.constants[index] {
+                        Object::CompiledFunction(function) => function.clone(),
+                        other => {
+                            let message = format!("not a function: {}", other.type_name());
+                            return Err(VmError::new(message));
+                        }
+                    };
+                    let free = self.stack.split_off(self.stack.len() - num_free);
+                    self.push(Object::Closure(Rc::new(Closure { function, free })))?;
+                }
+                Opcode::GetFree => {
+                    let index = self.read_u8_operand(operand_start);
+                    let value = self.current_frame().closure.free[index].clone();
+                    self.push(value)?;
+                }
+                Opcode::CurrentClosure => {
+                    let closure = self.current_frame().closure.clone();
+                    self.push(Object::Closure(closure))?;
+                }
+                Opcode::GetCell => {
+                    let value = match self.pop() {
+                        Object::Cell(cell) => match &*cell.value.borrow() {
+                            Some(value) => value.clone(),
+                            None => {
+                                let message = format!("identifier not found: {}", cell.name);
+                                return Err(VmError::new(message));
+                            }
+                        },
+                        other => other,
+                    };
+                    self.push(value)?;
+                }
+                Opcode::SetCell => {
+                    let cell = self.pop();
+                    let value = self.pop();
+                    if let Object::Cell(cell) = cell {
+                        *cell.value.borrow_mut() = Some(value);
+                    }
+                }
+                Opcode::Call => {
+                    let num_arguments = self.read_u8_operand(operand_start);
+                    self.call_function(num_arguments)?;
+                }
+                Opcode::ReturnValue => {
+                    let value = self.pop();
+                    if self.return_from_function(value) {
+                        return Ok(());
+                    }
+                }
+                Opcode::Return => {
+                    if self.return_from_function(Object::Null) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn call_function(&mut self, num_arguments: usize) -> Result<(), VmError> {
+        let callee = &self.stack[self.stack.len() - 1 - num_arguments];
+        let closure = match callee {
+            Object::Closure(closure) => closure.clone(),
+            Object::Builtin(builtin) => {
+                let builtin = builtin.clone();
+                let arguments = self.stack.split_off(self.stack.len() - num_arguments);
+                self.pop();
+                return match builtin.call(&arguments) {
+                    Ok(result) => self.push(result),
+                    Err(error) => Err(VmError::new(error.message)),
+                };
+            }
+            other => {
+                return Err(VmError::new(format!(
+                    "not a function: {}",
+                    other.type_name()
+                )))
+            }
+        };
+
+        let function = &closure.function;
+        if function.num_parameters != num_arguments {
+            return Err(VmError::new(format!(
+                "wrong number of arguments: want={}, got={}",
+                function.num_parameters, num_arguments
+            )));
+        }
+        // NOTE: the main program's frame isn't a call
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(VmError::new("stack overflow".to_string()));
+        }
+
+        let base_pointer = self.stack.len() - num_arguments;
+        let stack_top = base_pointer + function.num_locals;
+        if stack_top > STACK_SIZE {
+            return Err(VmError::new("stack overflow".to_string()));
+        }
+        self.stack.resize(stack_top, Object::Null);
+        for (index, name) in &function.cells {
+            let cell = Cell::new(name.clone());
+            self.stack[base_pointer + index] = Object::Cell(Rc::new(cell));
+        }
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base_pointer,
+        });
+        Ok(())
+    }
+
+    /// Pops the current frame and hands `value` to the caller. A `return` at
+    /// the top level ends the program instead; returns whether it did.
+    fn return_from_function(&mut self, value: Object<'static>) -> bool {
+        if self.frames.len() == 1 {
+            self.last_popped = Some(value);
+            return true;
+        }
+
+        let frame = self.frames.pop().unwrap();
+        // NOTE: the callee sits right below the arguments and goes too
+        self.stack.truncate(frame.base_pointer - 1);
+        self.stack.push(value);
+        false
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_u16_operand(&self, position: usize) -> usize {
+        let frame = self.frames.last().unwrap();
+        read_u16(&frame.closure.function.instructions[position..]) as usize
+    }
+
+    fn read_u8_operand(&self, position: usize) -> usize {
+        let frame = self.frames.last().unwrap();
+        frame.closure.function.instructions[position] as usize
+    }
+
+    fn push(&mut self, object: Object<'static>) -> Result<(), VmError> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(VmError::new("stack overflow".to_string()));
+        }
+        self.stack.push(object);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Object<'static> {
+        self.stack.pop().expect("stack underflow")
+    }
+}
+
+fn execute_binary_operation(
+    op: Opcode,
+    left: Object<'static>,
+    right: Object<'static>,
+) -> Result<Object<'static>, VmError> {
+    let operator = match op {
+        Opcode::Add => "+",
+        Opcode::Sub => "-",
+        Opcode::Mul => "*",
+        Opcode::Div => "/",
+        Opcode::Equal => "==",
+        Opcode::NotEqual => "!=",
+        Opcode::GreaterThan => ">",
+        Opcode::LessThan => "<",
+        _ => unreachable!("{:?} is not a binary operation", op),
+    };
+
+    match (&left, &right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            execute_integer_operation(op, *left, *right)
+        }
+        (Object::String(left), Object::String(right)) => {
+            execute_string_operation(op, operator, left, right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if op == Opcode::Equal => {
+            Ok(Object::Boolean(left == right))
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if op == Opcode::NotEqual => {
+            Ok(Object::Boolean(left != right))
+        }
+        (Object::Null, Object::Null) if op == Opcode::Equal => Ok(Object::Boolean(true)),
+        (Object::Null, Object::Null) if op == Opcode::NotEqual => Ok(Object::Boolean(false)),
+        _ if left.type_name() != right.type_name() => Err(VmError::new(format!(
+            "type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        ))),
+        _ => Err(VmError::new(format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        ))),
+    }
+}
+
+fn execute_integer_operation(
+    op: Opcode,
+    left: i32,
+    right: i32,
+) -> Result<Object<'static>, VmError> {
+    let result = match op {
+        Opcode::Add => Object::Integer(left.wrapping_add(right)),
+        Opcode::Sub => Object::Integer(left.wrapping_sub(right)),
+        Opcode::Mul => Object::Integer(left.wrapping_mul(right)),
+        Opcode::Div if right == 0 => return Err(VmError::new("division by zero".to_string())),
+        Opcode::Div => Object::Integer(left.wrapping_div(right)),
+        Opcode::Equal => Object::Boolean(left == right),
+        Opcode::NotEqual => Object::Boolean(left != right),
+        Opcode::GreaterThan => Object::Boolean(left > right),
+        Opcode::LessThan => Object::Boolean(left < right),
+        _ => unreachable!("{:?} is not a binary operation", op),
+    };
+    Ok(result)
+}
+
+fn execute_index_expression(
+    left: Object<'static>,
+    index: Object<'static>,
+) -> Result<Object<'static>, VmError> {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(index)) => Ok(usize::try_from(*index)
+            .ok()
+            .and_then(|index| elements.get(index))
+            .cloned()
+            .unwrap_or(Object::Null)),
+        (Object::Hash(hash), _) => match hash.get(&index) {
+            Ok(value) => Ok(value.cloned().unwrap_or(Object::Null)),
+            Err(message) => Err(VmError::new(message)),
+        },
+        _ => Err(VmError::new(format!(
+            "index operator not supported: {}[{}]",
+            left.type_name(),
+            index.type_name()
+        ))),
+    }
+}
+
+fn execute_string_operation(
+    op: Opcode,
+    operator: &str,
+    left: &str,
+    right: &str,
+) -> Result<Object<'static>, VmError> {
+    match op {
+        Opcode::Add => Ok(Object::String([left, right].concat().into())),
+        Opcode::Equal => Ok(Object::Boolean(left == right)),
+        Opcode::NotEqual => Ok(Object::Boolean(left != right)),
+        _ => Err(VmError::new(format!(
+            "unknown operator: STRING {} STRING",
+            operator
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::eval::tests::test_eval;
+    use crate::parser::parse;
+
+    fn run(input: &str) -> String {
+        let program = parse(input).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+
+        let mut vm = Vm::new(compiler.bytecode());
+        match vm.run() {
+            Ok(()) => match vm.last_popped_stack_elem() {
+                Some(object) => object.to_string(),
+                None => Object::Null.to_string(),
+            },
+            Err(error) => format!("ERROR: {}", error),
+        }
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let tests = [
+            ("1", "1"),
+            ("1 + 2", "3"),
+            ("4 / 2", "2"),
+            ("50 / 2 * 2 + 10 - 5", "55"),
+            ("5 * (2 + 10)", "60"),
+            ("-50 + 100 + -50", "0"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("2147483647 + 1", "-2147483648"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn conditionals_and_globals() {
+        let tests = [
+            ("if (true) { 10 }", "10"),
+            ("if (false) { 10 }", "null"),
+            ("if (1 > 2) { 10 } else { 20 }", "20"),
+            ("if ((if (false) { 10 })) { 10 } else { 20 }", "20"),
+            ("let one = 1; let two = one + one; one + two", "3"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn calling_functions() {
+        let tests = [
+            ("let f = fn() { 5 + 10; }; f();", "15"),
+            ("let f = fn() { return 99; 100; }; f();", "99"),
+            ("let f = fn() { }; f();", "null"),
+            ("let f = fn(a, b) { let c = a + b; c * 2 }; f(1, 2);", "6"),
+            (
+                "let f = fn(a) { a }; let g = fn(a) { f(a) + f(a) }; g(4)",
+                "8",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn closures() {
+        let tests = [
+            (
+                "let makeAdder = fn(x) { fn(y) { x + y } }; makeAdder(2)(3)",
+                "5",
+            ),
+            (
+                "let f = fn(a) { fn(b) { fn(c) { a + b + c } } }; f(1)(2)(3)",
+                "6",
+            ),
+            (
+                "let f = fn(a) { let b = a * 2; fn() { let c = 1; fn() { a + b + c } } }; f(1)()()",
+                "4",
+            ),
+            (
+                "let wrapper = fn() {
+                   let countDown = fn(x) { if (x == 0) { 0 } else { countDown(x - 1) } };
+                   countDown(5)
+                 };
+                 wrapper()",
+                "0",
+            ),
+            ("let x = 1; let x = x + 1; x", "2"),
+            ("let f = fn() { let a = 1; let a = a + 1; a }; f()", "2"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let tests = [
+            (
+                "fn(a) { a }()",
+                "ERROR: wrong number of arguments: want=1, got=0",
+            ),
+            ("let f = fn() { f() }; f()", "ERROR: stack overflow"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn same_results_as_eval() {
+        let tests = [
+            "5 + 5 * 2 - 10 / 2",
+            "-(3 * 4) + 20",
+            "1 < 2 == true",
+            "!!5",
+            "!(1 > 2)",
+            "true != false",
+            "if (1 < 2) { 10 } else { 20 }",
+            "if (false) { 10 }",
+            "return 10; 9;",
+            "9; return 2 * 5; 9;",
+            "if (10 > 1) { if (10 > 1) { return 10; } return 1; }",
+            "let a = 5; let b = a; let c = a + b + 5; c;",
+            "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "5 + true;",
+            "-true",
+            "true + false;",
+            "if (10 > 1) { true + false; }",
+            "10 / (5 - 5)",
+            "5(1)",
+            "1 < true",
+            r#""mon" + "key""#,
+            r#"let greet = fn(name) { "hi " + name }; greet("you") == "hi you""#,
+            r#""a" != "a""#,
+            r#""a" * "b""#,
+            r#""a" + 1"#,
+            "[1, 2 * 2, 3 + 3]",
+            "let a = [1, 2, 3]; let i = a[0]; a[i] + a[2]",
+            "[[1, 2], [3]][0][1]",
+            "[1, 2, 3][3]",
+            "[1, 2, 3][-1]",
+            "1[0]",
+            r#"len("héllo") + len([1, 2])"#,
+            "let a = [1]; let b = push(a, 2); [a, b, first(b), last(b), rest(b)]",
+            "first([])",
+            "rest(1)",
+            "len(1, 2)",
+            "let len = fn(x) { 42 }; len([])",
+            "let f = fn(a) { let first = 1; first + len(a) }; f([5])",
+            r#"let two = "two"; {"one": 10 - 9, two: 1 + 1, 3: true, true: 4}"#,
+            r#"let h = {"foo": 5, 1: [2]}; [h["foo"], h[1][0], h["bar"]]"#,
+            "{1: 1, 1: 2}",
+            r#"{"name": "Monkey"}[fn(x) { x }]"#,
+            "{[1]: 2}",
+            "let makeAdder = fn(x) { fn(y) { x + y } }; makeAdder(2)(3)",
+            "fn(f) { f(f(1)) }(fn(x) { x * 10 })",
+            "let compose = fn(f, g) { fn(x) { g(f(x)) } }; compose(fn(x) { x + 1 }, fn(x) { x * 2 })(5)",
+            "let counter = fn(n) { if (n == 0) { 0 } else { 1 + counter(n - 1) } }; counter(50)",
+            crate::eval::tests::MAP_REDUCE,
+            "let add = fn(a, b) { a + b }; add(1)",
+            "fn() { 1 }(1, 2)",
+            "let f = fn() { f() }; f()",
+            "x",
+            "let f = fn() { g() }; let g = 1; f()",
+            "let f = fn() { g() }; f(); let g = 1;",
+            "let f = fn() { g() }; let g = fn() { 2 }; f()",
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             odd(7)",
+            "let outer = fn() {
+               let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+               let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+               even(10)
+             };
+             outer()",
+            "let f = fn() { let g = fn() { x }; let x = 3; g() }; f()",
+            "let f = fn() { let g = fn() { x }; let r = g(); let x = 3; r }; f()",
+            "let f = fn() { let g = fn() { x }; let x = 1; let x = x + 1; g() }; f()",
+            "let f = fn() { let g = fn() { fn() { x } }; let x = 4; g()() }; f()",
+            "let f = fn() { let g = fn() { x }; g() }; f()",
+        ];
+
+        for input in tests {
+            assert_eq!(run(input), test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn recursion_as_deep_as_eval() {
+        let counter = "let counter = fn(n) { if (n == 0) { 0 } else { 1 + counter(n - 1) } };";
+        let tests = [
+            (format!("{} counter(300)", counter), "300".to_string()),
+            (
+                format!("{} counter({})", counter, MAX_CALL_DEPTH - 1),
+                (MAX_CALL_DEPTH - 1).to_string(),
+            ),
+            (
+                format!("{} counter({})", counter, MAX_CALL_DEPTH),
+                "ERROR: stack overflow".to_string(),
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(&input), expected, "{}", input);
+            assert_eq!(test_eval(&input), expected, "{}", input);
+        }
+
+        let elements: Vec<String> = (1..=1000).map(|n| n.to_string()).collect();
+        let array = format!("[{}]", elements.join(", "));
+        let input = crate::eval::tests::MAP_REDUCE.replace("[1, 2, 3, 4]", &array);
+        assert!(run(&input).ends_with(", 2000], 500500]"), "{}", run(&input));
+        assert_eq!(run(&input), test_eval(&input));
+    }
+
+    #[test]
+    fn custom_builtins() {
+        let mut builtins = Builtins::new();
+        builtins.register("answer", 0, |_: &[Object]| Ok(Object::Integer(42)));
+
+        let mut compiler = Compiler::with_builtins(&builtins);
+        compiler.compile(&parse("answer() + 1").unwrap()).unwrap();
+        let mut vm = Vm::with_builtins(compiler.bytecode(), builtins.clone());
+        vm.run().unwrap();
+        assert_eq!(vm.last_popped_stack_elem().unwrap().to_string(), "43");
+
+        compiler.compile(&parse("len([])").unwrap()).unwrap();
+        let mut vm = Vm::with_builtins(compiler.bytecode(), builtins);
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.to_string(), "identifier not found: len");
+    }
+}